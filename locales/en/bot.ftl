@@ -0,0 +1,120 @@
+# Name of the language, shown in /settings
+language-name = English
+
+## Commands registered with Telegram
+
+command-start = Display a brief introduction to the bot
+command-help = Display command list and usage information
+command-single = Start single sticker export mode, optionally with an output format
+command-pack = Start pack export mode, optionally with an output and archive format
+command-settings = Change your export preferences
+command-cancel = Cancel the current operation
+command-export = Export the sticker or custom emoji in the replied message, optionally with an output format
+command-exportpack = Export the pack of the sticker in the replied message, optionally with an output and archive format
+command-enableexport = Allow exports in this group (administrators only)
+command-disableexport = Disallow exports in this group (administrators only)
+
+## /start and /help, in Telegram HTML
+
+start =
+    I am a sticker export bot that can help you export a sticker or an entire sticker pack.
+    You can use the following commands to enter different modes:
+
+    /single - Export a single sticker
+    /pack - Export an entire sticker pack
+
+    Both commands accept an output format, e.g. <code>/pack webp</code>, which can also be changed with the buttons below the mode message.
+    /pack also accepts an archive format, e.g. <code>/pack webp tar.zst</code>.
+    Use /settings to change the default formats, file names, animation size and frame rate, and the language.
+
+    Instead of a sticker, you can also send a message with custom emoji: they are exported one by one, or as their whole emoji sets in pack mode.
+
+    You can also use the /cancel command to cancel the current operation.
+
+    This bot is open source. You can find the source code on <a href="https://github.com/AH-dark/telegram-sticker-export-bot">AH-dark/telegram-sticker-export-bot</a>. If you have any questions or suggestions, please feel free to open an issue or pull request. If you like this bot, please give it a star. Thank you!
+help =
+    <b>Available commands:</b>
+
+    /start - Display a brief introduction to the bot
+    /help - Display command list and usage information
+    /single [format] - Start single sticker export mode
+    /pack [format] [archive] [link] - Start pack export mode, or export the linked packs right away
+    /settings - Change your export preferences
+    /cancel - Cancel the current operation
+
+    You can also use me inline: type my username followed by a sticker pack link or name in any chat.
+
+    <b>Output formats:</b>
+
+    png, webp - Still images, animated stickers export their first frame
+    gif, apng, awebp, mp4 - Animations, static stickers are exported as PNG or WebP
+    original - The file as stored by Telegram
+    lottie, dotlottie - Vector sources of animated stickers
+
+    <b>Archive formats:</b>
+
+    zip (default), zip-store (uncompressed), tar.gz, tar.zst
+
+## Export modes
+
+canceled = Operation canceled.
+single-mode = Single export mode ({ $format }), please send me stickers.
+pack-mode = Pack export mode ({ $format }, { $archive }), please send me stickers from the sticker pack you want to export.
+choose-mode-first = Please choose an export mode first.
+format-set = Output format set to { $format }.
+unknown-format =
+    Unknown format or sticker pack. Available output formats: { $formats }
+    Available archive formats (/pack only): { $archives }
+send-sticker =
+    You need to send me a sticker or custom emoji to export. Please send me a sticker and try again.
+    In pack mode, you can also send a sticker pack link such as https://t.me/addstickers/name, or just the pack name.
+    If you want to quit the current operation, you can use the /cancel command.
+send-sticker-from-pack = Please send me a sticker from a sticker pack.
+state-error = Failed to get state, state manager error: { $error }
+rate-limited = Rate limit exceeded.
+
+## Export progress
+
+job-queued = Export #{ $id } is queued, position { $position }.
+job-cancel = Cancel
+job-canceled = Export #{ $id } canceled.
+job-not-found = This export has already finished or was started by someone else.
+processing = Processing...
+downloading = Downloading... { $done }/{ $total }
+uploading = Uploading archive...
+summary = { $exported }/{ $total } exported
+summary-failures = { $exported }/{ $total } exported, see errors.txt for the stickers that failed
+export-failed = Export failed: { $error }
+
+## /settings
+
+settings-intro = Your export settings, they apply to every export unless a command says otherwise. Formats and file names left at default follow the bot's defaults.
+settings-format = Output format
+settings-archive = Archive format
+settings-template = File names
+settings-width = Animation width
+settings-fps = Frame rate
+settings-manifest = Manifest
+settings-language = Language
+settings-on = on
+settings-off = off
+settings-default = Default ({ $value })
+settings-language-auto = Automatic
+settings-back = « Back
+
+## Inline mode
+
+inline-export = Export as { $archive }
+inline-description = { $title } ({ $count ->
+        [one] { $count } sticker
+       *[other] { $count } stickers
+    })
+inline-switch-pm = Export { $title }
+
+## Group chats
+
+group-reply-required = Reply to a sticker or a message with custom emoji to export it.
+group-no-sticker = The replied message has no sticker or custom emoji.
+group-admin-only = Only group administrators can change this setting.
+group-enabled = Sticker exports are now enabled in this group.
+group-disabled = Sticker exports are now disabled in this group.
@@ -0,0 +1,121 @@
+# Name of the language, shown in /settings
+language-name = Русский
+
+## Commands registered with Telegram
+
+command-start = Краткое описание бота
+command-help = Список команд и справка
+command-single = Режим экспорта отдельных стикеров, можно указать формат
+command-pack = Режим экспорта наборов, можно указать формат и тип архива
+command-settings = Изменить настройки экспорта
+command-cancel = Отменить текущую операцию
+command-export = Экспортировать стикер или эмодзи из сообщения, на которое вы ответили, можно указать формат
+command-exportpack = Экспортировать набор стикера из сообщения, на которое вы ответили, можно указать формат и тип архива
+command-enableexport = Разрешить экспорт в этой группе (только администраторы)
+command-disableexport = Запретить экспорт в этой группе (только администраторы)
+
+## /start and /help, in Telegram HTML
+
+start =
+    Я бот для экспорта стикеров: помогу сохранить отдельный стикер или целый набор.
+    Используйте следующие команды, чтобы выбрать режим:
+
+    /single - Экспорт отдельного стикера
+    /pack - Экспорт всего набора стикеров
+
+    Обе команды принимают формат, например <code>/pack webp</code>, его также можно изменить кнопками под сообщением режима.
+    /pack также принимает тип архива, например <code>/pack webp tar.zst</code>.
+    В /settings можно изменить форматы по умолчанию, имена файлов, размер и частоту кадров анимаций, а также язык.
+
+    Вместо стикера можно отправить сообщение с пользовательскими эмодзи: они экспортируются по одному или целыми наборами в режиме наборов.
+
+    Команда /cancel отменяет текущую операцию.
+
+    Это бот с открытым исходным кодом: <a href="https://github.com/AH-dark/telegram-sticker-export-bot">AH-dark/telegram-sticker-export-bot</a>. Если у вас есть вопросы или предложения, создайте issue или pull request. Если бот вам нравится, поставьте ему звезду. Спасибо!
+help =
+    <b>Доступные команды:</b>
+
+    /start - Краткое описание бота
+    /help - Список команд и справка
+    /single [формат] - Режим экспорта отдельных стикеров
+    /pack [формат] [архив] [ссылка] - Режим экспорта наборов, или сразу экспортировать наборы по ссылкам
+    /settings - Изменить настройки экспорта
+    /cancel - Отменить текущую операцию
+
+    Меня можно использовать и в инлайн-режиме: введите моё имя пользователя и ссылку на набор или его название в любом чате.
+
+    <b>Форматы:</b>
+
+    png, webp - Статичные изображения, из анимированных стикеров берётся первый кадр
+    gif, apng, awebp, mp4 - Анимации, статичные стикеры экспортируются в PNG или WebP
+    original - Файл в том виде, в котором его хранит Telegram
+    lottie, dotlottie - Векторные исходники анимированных стикеров
+
+    <b>Типы архивов:</b>
+
+    zip (по умолчанию), zip-store (без сжатия), tar.gz, tar.zst
+
+## Export modes
+
+canceled = Операция отменена.
+single-mode = Режим экспорта отдельных стикеров ({ $format }), отправьте мне стикеры.
+pack-mode = Режим экспорта наборов ({ $format }, { $archive }), отправьте мне стикер из набора, который хотите экспортировать.
+choose-mode-first = Сначала выберите режим экспорта.
+format-set = Формат изменён на { $format }.
+unknown-format =
+    Неизвестный формат или набор стикеров. Доступные форматы: { $formats }
+    Доступные типы архивов (только для /pack): { $archives }
+send-sticker =
+    Отправьте мне стикер или пользовательский эмодзи для экспорта и попробуйте ещё раз.
+    В режиме наборов можно также отправить ссылку на набор, например https://t.me/addstickers/name, или просто его название.
+    Чтобы выйти из текущей операции, используйте команду /cancel.
+send-sticker-from-pack = Отправьте мне стикер из набора стикеров.
+state-error = Не удалось получить состояние, ошибка хранилища: { $error }
+rate-limited = Слишком много запросов, попробуйте позже.
+
+## Export progress
+
+job-queued = Экспорт #{ $id } в очереди, позиция { $position }.
+job-cancel = Отменить
+job-canceled = Экспорт #{ $id } отменён.
+job-not-found = Этот экспорт уже завершён или был запущен другим пользователем.
+processing = Обработка...
+downloading = Загрузка... { $done }/{ $total }
+uploading = Отправка архива...
+summary = Экспортировано { $exported }/{ $total }
+summary-failures = Экспортировано { $exported }/{ $total }, стикеры с ошибками перечислены в errors.txt
+export-failed = Не удалось экспортировать: { $error }
+
+## /settings
+
+settings-intro = Ваши настройки экспорта, они применяются ко всем экспортам, если в команде не указано иное. Форматы и имена файлов по умолчанию следуют настройкам бота.
+settings-format = Формат
+settings-archive = Тип архива
+settings-template = Имена файлов
+settings-width = Ширина анимаций
+settings-fps = Частота кадров
+settings-manifest = Манифест
+settings-language = Язык
+settings-on = вкл
+settings-off = выкл
+settings-default = По умолчанию ({ $value })
+settings-language-auto = Автоматически
+settings-back = « Назад
+
+## Inline mode
+
+inline-export = Экспорт в { $archive }
+inline-description = { $title } ({ $count ->
+        [one] { $count } стикер
+        [few] { $count } стикера
+       *[many] { $count } стикеров
+    })
+inline-switch-pm = Экспортировать { $title }
+
+## Group chats
+
+group-reply-required = Ответьте этой командой на стикер или сообщение с пользовательскими эмодзи.
+group-no-sticker = В сообщении нет стикеров или пользовательских эмодзи.
+group-admin-only = Эту настройку могут менять только администраторы группы.
+group-enabled = Экспорт стикеров в этой группе включён.
+group-disabled = Экспорт стикеров в этой группе выключен.
@@ -0,0 +1,117 @@
+# Name of the language, shown in /settings
+language-name = 中文
+
+## Commands registered with Telegram
+
+command-start = 显示机器人的简介
+command-help = 显示命令列表和使用说明
+command-single = 进入单个贴纸导出模式，可指定输出格式
+command-pack = 进入贴纸包导出模式，可指定输出格式和压缩包格式
+command-settings = 修改导出偏好设置
+command-cancel = 取消当前操作
+command-export = 导出所回复消息中的贴纸或自定义表情，可指定输出格式
+command-exportpack = 导出所回复消息中贴纸所在的贴纸包，可指定输出格式和压缩包格式
+command-enableexport = 允许在本群导出（仅限管理员）
+command-disableexport = 禁止在本群导出（仅限管理员）
+
+## /start and /help, in Telegram HTML
+
+start =
+    我是一个贴纸导出机器人，可以帮你导出单个贴纸或整个贴纸包。
+    你可以使用以下命令进入不同的模式：
+
+    /single - 导出单个贴纸
+    /pack - 导出整个贴纸包
+
+    两个命令都可以指定输出格式，例如 <code>/pack webp</code>，也可以通过模式消息下方的按钮更改。
+    /pack 还可以指定压缩包格式，例如 <code>/pack webp tar.zst</code>。
+    使用 /settings 更改默认格式、文件名、动画尺寸、帧率以及语言。
+
+    除了贴纸，你也可以发送包含自定义表情的消息：单个模式下逐个导出，贴纸包模式下导出它们所在的整个表情包。
+
+    你可以随时使用 /cancel 命令取消当前操作。
+
+    本机器人是开源的，源代码位于 <a href="https://github.com/AH-dark/telegram-sticker-export-bot">AH-dark/telegram-sticker-export-bot</a>。如有任何问题或建议，欢迎提交 issue 或 pull request。如果你喜欢这个机器人，请给它点个 star，谢谢！
+help =
+    <b>可用命令：</b>
+
+    /start - 显示机器人的简介
+    /help - 显示命令列表和使用说明
+    /single [格式] - 进入单个贴纸导出模式
+    /pack [格式] [压缩包] [链接] - 进入贴纸包导出模式，或直接导出链接中的贴纸包
+    /settings - 修改导出偏好设置
+    /cancel - 取消当前操作
+
+    你也可以内联使用我：在任意聊天中输入我的用户名，后接贴纸包链接或名称。
+
+    <b>输出格式：</b>
+
+    png, webp - 静态图片，动态贴纸导出第一帧
+    gif, apng, awebp, mp4 - 动画，静态贴纸导出为 PNG 或 WebP
+    original - Telegram 存储的原始文件
+    lottie, dotlottie - 动态贴纸的矢量源文件
+
+    <b>压缩包格式：</b>
+
+    zip（默认）, zip-store（不压缩）, tar.gz, tar.zst
+
+## Export modes
+
+canceled = 操作已取消。
+single-mode = 单个贴纸导出模式（{ $format }），请发送贴纸。
+pack-mode = 贴纸包导出模式（{ $format }，{ $archive }），请发送你想导出的贴纸包中的贴纸。
+choose-mode-first = 请先选择导出模式。
+format-set = 输出格式已设为 { $format }。
+unknown-format =
+    未知的格式或贴纸包。可用的输出格式：{ $formats }
+    可用的压缩包格式（仅限 /pack）：{ $archives }
+send-sticker =
+    请发送要导出的贴纸或自定义表情，然后重试。
+    在贴纸包模式下，你也可以发送贴纸包链接，例如 https://t.me/addstickers/name，或者直接发送贴纸包名称。
+    如果想退出当前操作，可以使用 /cancel 命令。
+send-sticker-from-pack = 请发送一个来自贴纸包的贴纸。
+state-error = 获取状态失败，状态管理器错误：{ $error }
+rate-limited = 请求过于频繁，请稍后再试。
+
+## Export progress
+
+job-queued = 导出任务 #{ $id } 正在排队，位置 { $position }。
+job-cancel = 取消
+job-canceled = 导出任务 #{ $id } 已取消。
+job-not-found = 该导出任务已完成，或由其他用户发起。
+processing = 处理中...
+downloading = 下载中... { $done }/{ $total }
+uploading = 正在上传压缩包...
+summary = 已导出 { $exported }/{ $total }
+summary-failures = 已导出 { $exported }/{ $total }，导出失败的贴纸见 errors.txt
+export-failed = 导出失败：{ $error }
+
+## /settings
+
+settings-intro = 你的导出设置，除非命令中另行指定，否则适用于所有导出。保持默认的格式和文件名将跟随机器人的默认设置。
+settings-format = 输出格式
+settings-archive = 压缩包格式
+settings-template = 文件名
+settings-width = 动画宽度
+settings-fps = 帧率
+settings-manifest = 清单文件
+settings-language = 语言
+settings-on = 开
+settings-off = 关
+settings-default = 默认（{ $value }）
+settings-language-auto = 自动
+settings-back = « 返回
+
+## Inline mode
+
+inline-export = 导出为 { $archive }
+inline-description = { $title }（{ $count } 个贴纸）
+inline-switch-pm = 导出 { $title }
+
+## Group chats
+
+group-reply-required = 请回复一个贴纸或包含自定义表情的消息来导出。
+group-no-sticker = 所回复的消息中没有贴纸或自定义表情。
+group-admin-only = 只有群管理员可以更改此设置。
+group-enabled = 本群已允许导出贴纸。
+group-disabled = 本群已禁止导出贴纸。
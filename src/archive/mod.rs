@@ -0,0 +1,387 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+pub(crate) mod tarball;
+pub(crate) mod zipfile;
+
+/// Upload limit of the public Bot API, in bytes.
+pub const DEFAULT_SPLIT_SIZE: u64 = 50_000_000;
+
+/// Container format of pack exports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    /// Deflate-compressed zip.
+    #[default]
+    Zip,
+    /// Zip without compression, most sticker formats are already compressed.
+    ZipStored,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    pub const ALL: [ArchiveFormat; 4] = [
+        ArchiveFormat::Zip,
+        ArchiveFormat::ZipStored,
+        ArchiveFormat::TarGz,
+        ArchiveFormat::TarZst,
+    ];
+
+    /// The identifier used in command arguments.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::ZipStored => "zip-store",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip | ArchiveFormat::ZipStored => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Start an archive of this format in the given file.
+    fn writer(&self, file: File) -> anyhow::Result<Box<dyn ArchiveWriter>> {
+        Ok(match self {
+            ArchiveFormat::Zip => Box::new(zipfile::ZipArchiveWriter::new(file, true)),
+            ArchiveFormat::ZipStored => Box::new(zipfile::ZipArchiveWriter::new(file, false)),
+            ArchiveFormat::TarGz => Box::new(tarball::TarArchiveWriter::gzip(file)),
+            ArchiveFormat::TarZst => Box::new(tarball::TarArchiveWriter::zstd(file)?),
+        })
+    }
+}
+
+impl Display for ArchiveFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArchiveFormat::Zip => "ZIP",
+            ArchiveFormat::ZipStored => "ZIP (uncompressed)",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+        })
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "zip" => Ok(ArchiveFormat::Zip),
+            "zip-store" | "zip_store" | "store" => Ok(ArchiveFormat::ZipStored),
+            "tar.gz" | "tgz" | "targz" => Ok(ArchiveFormat::TarGz),
+            "tar.zst" | "tzst" | "tarzst" => Ok(ArchiveFormat::TarZst),
+            _ => Err(anyhow::anyhow!("Unknown archive format: {}", s)),
+        }
+    }
+}
+
+/// A container that files are streamed into, implemented once per [`ArchiveFormat`].
+pub trait ArchiveWriter: Send {
+    /// Upper bound of the space a file takes up in the archive, headers included.
+    fn entry_size(&self, name: &str, len: u64) -> u64;
+
+    /// Upper bound of the space taken up by an empty archive.
+    fn trailer_size(&self) -> u64;
+
+    fn add_file(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()>;
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// An archive written to disk as it is built, split into numbered parts
+/// so that no part exceeds the size limit.
+pub struct SplitArchive {
+    dir: TempDir,
+    format: ArchiveFormat,
+    split_size: u64,
+    parts: Vec<PathBuf>,
+    current: Option<Box<dyn ArchiveWriter>>,
+    /// Upper bound of the size of the current part once finished.
+    current_size: u64,
+}
+
+impl SplitArchive {
+    pub fn new(format: ArchiveFormat, split_size: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: tempfile::tempdir().context("Failed to create a temporary directory")?,
+            format,
+            split_size,
+            parts: Vec::new(),
+            current: None,
+            current_size: 0,
+        })
+    }
+
+    /// Add a file to the archive, starting a new part if it doesn't fit in the current one.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        // a file that is too large on its own still gets a part of its own
+        if let Some(writer) = &self.current {
+            if self.current_size + writer.entry_size(name, data.len() as u64) > self.split_size {
+                self.finish_part()?;
+            }
+        }
+
+        let writer = match &mut self.current {
+            Some(writer) => writer,
+            None => {
+                let path = self.dir.path().join(format!(
+                    "part{}.{}",
+                    self.parts.len() + 1,
+                    self.format.extension()
+                ));
+                let file = File::create(&path).context("Failed to create archive part")?;
+                self.parts.push(path);
+
+                let writer = self.format.writer(file)?;
+                self.current_size = writer.trailer_size();
+                self.current.insert(writer)
+            }
+        };
+
+        self.current_size += writer.entry_size(name, data.len() as u64);
+        writer.add_file(name, data)
+    }
+
+    /// Finish the archive, returning its parts in order.
+    pub fn finish(mut self) -> anyhow::Result<ArchiveParts> {
+        self.finish_part()?;
+
+        Ok(ArchiveParts {
+            _dir: self.dir,
+            paths: self.parts,
+        })
+    }
+
+    fn finish_part(&mut self) -> anyhow::Result<()> {
+        if let Some(writer) = self.current.take() {
+            writer.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A [`SplitArchive`] written by a blocking task, so compression and disk writes
+/// don't hold up the async runtime.
+///
+/// Dropping it before [`ArchiveTask::finish`] stops the task, which then removes the parts.
+pub struct ArchiveTask {
+    files: mpsc::Sender<(String, Vec<u8>)>,
+    task: JoinHandle<anyhow::Result<ArchiveParts>>,
+}
+
+impl ArchiveTask {
+    pub fn spawn(format: ArchiveFormat, split_size: u64) -> anyhow::Result<Self> {
+        let mut archive = SplitArchive::new(format, split_size)?;
+        // a few files of buffer keep conversions going while the writer catches up
+        let (files, mut receiver) = mpsc::channel::<(String, Vec<u8>)>(4);
+
+        let task = tokio::task::spawn_blocking(move || {
+            while let Some((name, data)) = receiver.blocking_recv() {
+                archive.add_file(&name, &data)?;
+            }
+
+            archive.finish()
+        });
+
+        Ok(Self { files, task })
+    }
+
+    /// Queue a file to be added to the archive.
+    pub async fn add_file(&mut self, name: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        if self.files.send((name.to_string(), data)).await.is_err() {
+            // the writer only stops early when it fails
+            (&mut self.task)
+                .await
+                .context("Archive writer panicked")??;
+            return Err(anyhow::anyhow!("Archive writer stopped unexpectedly"));
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the queued files to be written and finish the archive, returning its parts in order.
+    pub async fn finish(self) -> anyhow::Result<ArchiveParts> {
+        drop(self.files);
+
+        self.task.await.context("Archive writer panicked")?
+    }
+}
+
+/// The finished parts of a [`SplitArchive`], removed from disk when dropped.
+pub struct ArchiveParts {
+    _dir: TempDir,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Worst case growth of compressing incompressible data, plus a little slack.
+fn compression_overhead(len: u64) -> u64 {
+    len / 1000 + 64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::Path;
+
+    use super::*;
+
+    /// Data that doesn't compress, like most converted stickers.
+    pub(super) fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// The files of an archive, in order.
+    pub(super) fn read_archive(format: ArchiveFormat, path: &Path) -> Vec<(String, Vec<u8>)> {
+        let file = File::open(path).unwrap();
+        match format {
+            ArchiveFormat::Zip | ArchiveFormat::ZipStored => {
+                let mut zip = zip::ZipArchive::new(file).unwrap();
+                (0..zip.len())
+                    .map(|i| {
+                        let mut entry = zip.by_index(i).unwrap();
+                        let mut data = Vec::new();
+                        entry.read_to_end(&mut data).unwrap();
+                        (entry.name().to_string(), data)
+                    })
+                    .collect()
+            }
+            ArchiveFormat::TarGz => read_tar(flate2::read::GzDecoder::new(file)),
+            ArchiveFormat::TarZst => read_tar(zstd::Decoder::new(file).unwrap()),
+        }
+    }
+
+    fn read_tar(reader: impl Read) -> Vec<(String, Vec<u8>)> {
+        tar::Archive::new(reader)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (entry.path().unwrap().to_str().unwrap().to_string(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_parts_under_the_size_limit() {
+        let files = (0..12)
+            .map(|i| {
+                (
+                    format!("{:02}.webp", i + 1),
+                    noise(20_000 + i * 1_000, i as u32),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for format in ArchiveFormat::ALL {
+            let split_size = 100_000;
+            let mut archive = SplitArchive::new(format, split_size).unwrap();
+            for (name, data) in &files {
+                archive.add_file(name, data).unwrap();
+            }
+            let parts = archive.finish().unwrap();
+
+            assert!(parts.paths.len() > 1, "{} wasn't split", format);
+            for (i, path) in parts.paths.iter().enumerate() {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                assert_eq!(name, format!("part{}.{}", i + 1, format.extension()));
+                let size = path.metadata().unwrap().len();
+                assert!(
+                    size <= split_size,
+                    "{} part {} is {} bytes",
+                    format,
+                    i + 1,
+                    size
+                );
+            }
+
+            // the parts hold every file once, in order
+            let read = parts
+                .paths
+                .iter()
+                .flat_map(|path| read_archive(format, path))
+                .collect::<Vec<_>>();
+            assert_eq!(read, files, "{} lost files", format);
+        }
+    }
+
+    #[test]
+    fn gives_oversized_files_a_part_of_their_own() {
+        let small = noise(1_000, 1);
+        let large = noise(30_000, 2);
+
+        let mut archive = SplitArchive::new(ArchiveFormat::ZipStored, 10_000).unwrap();
+        archive.add_file("1.png", &small).unwrap();
+        archive.add_file("2.png", &large).unwrap();
+        archive.add_file("3.png", &small).unwrap();
+        let parts = archive.finish().unwrap();
+
+        let read = parts
+            .paths
+            .iter()
+            .map(|path| read_archive(ArchiveFormat::ZipStored, path))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            read,
+            [
+                vec![("1.png".to_string(), small.clone())],
+                vec![("2.png".to_string(), large)],
+                vec![("3.png".to_string(), small)],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_archives_on_a_blocking_task() {
+        let mut archive = ArchiveTask::spawn(ArchiveFormat::TarGz, DEFAULT_SPLIT_SIZE).unwrap();
+        archive.add_file("1.gif", noise(100, 1)).await.unwrap();
+        archive.add_file("2.gif", noise(200, 2)).await.unwrap();
+        let parts = archive.finish().await.unwrap();
+
+        assert_eq!(parts.paths.len(), 1);
+        assert_eq!(
+            read_archive(ArchiveFormat::TarGz, &parts.paths[0]),
+            [
+                ("1.gif".to_string(), noise(100, 1)),
+                ("2.gif".to_string(), noise(200, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_format_names() {
+        for format in ArchiveFormat::ALL {
+            assert_eq!(format.name().parse::<ArchiveFormat>().unwrap(), format);
+        }
+        assert_eq!(
+            "TGZ".parse::<ArchiveFormat>().unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert!("rar".parse::<ArchiveFormat>().is_err());
+    }
+}
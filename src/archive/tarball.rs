@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::Write;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tar::{Builder, Header};
+
+use crate::archive::{compression_overhead, ArchiveWriter};
+
+/// Size of a tar block, headers and file data are padded to it.
+const BLOCK_SIZE: u64 = 512;
+
+/// Tar archive, compressed as a whole by the encoder `W`.
+pub struct TarArchiveWriter<W: Write> {
+    tar: Builder<W>,
+    finish_encoder: fn(W) -> std::io::Result<File>,
+    mtime: u64,
+}
+
+impl TarArchiveWriter<GzEncoder<File>> {
+    pub fn gzip(file: File) -> Self {
+        Self::new(
+            GzEncoder::new(file, Compression::default()),
+            GzEncoder::finish,
+        )
+    }
+}
+
+impl TarArchiveWriter<zstd::Encoder<'static, File>> {
+    pub fn zstd(file: File) -> anyhow::Result<Self> {
+        // level 0 picks zstd's default level
+        let encoder = zstd::Encoder::new(file, 0).context("Failed to create zstd encoder")?;
+
+        Ok(Self::new(encoder, zstd::Encoder::finish))
+    }
+}
+
+impl<W: Write> TarArchiveWriter<W> {
+    fn new(encoder: W, finish_encoder: fn(W) -> std::io::Result<File>) -> Self {
+        Self {
+            tar: Builder::new(encoder),
+            finish_encoder,
+            mtime: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl<W: Write + Send> ArchiveWriter for TarArchiveWriter<W> {
+    /// Covers the header, a GNU long name entry for names that don't fit in it,
+    /// and the padding of the data to whole blocks.
+    fn entry_size(&self, name: &str, len: u64) -> u64 {
+        let long_name = if name.len() >= 100 {
+            BLOCK_SIZE + (name.len() as u64 + 1).div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+        } else {
+            0
+        };
+
+        BLOCK_SIZE + long_name + len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE + compression_overhead(len)
+    }
+
+    /// The end of archive marker is two empty blocks.
+    fn trailer_size(&self) -> u64 {
+        2 * BLOCK_SIZE + compression_overhead(0)
+    }
+
+    fn add_file(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+
+        self.tar
+            .append_data(&mut header, name, data)
+            .context("Failed to write file to tar archive")
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        let Self {
+            tar,
+            finish_encoder,
+            ..
+        } = *self;
+
+        let encoder = tar.into_inner().context("Failed to finish tar archive")?;
+        finish_encoder(encoder).context("Failed to finish compressed stream")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::{noise, read_archive};
+    use crate::archive::ArchiveFormat;
+
+    #[test]
+    fn round_trips_files() {
+        for format in [ArchiveFormat::TarGz, ArchiveFormat::TarZst] {
+            let files = [
+                ("01_😺.webm".to_string(), noise(5_000, 1)),
+                // longer than the name field of the header
+                (format!("{}.webm", "😺".repeat(40)), noise(700, 2)),
+                ("errors.txt".to_string(), Vec::new()),
+            ];
+
+            let file = tempfile::NamedTempFile::new().unwrap();
+            let output = file.reopen().unwrap();
+            let mut writer: Box<dyn ArchiveWriter> = match format {
+                ArchiveFormat::TarGz => Box::new(TarArchiveWriter::gzip(output)),
+                _ => Box::new(TarArchiveWriter::zstd(output).unwrap()),
+            };
+            let mut bound = writer.trailer_size();
+            for (name, data) in &files {
+                bound += writer.entry_size(name, data.len() as u64);
+                writer.add_file(name, data).unwrap();
+            }
+            writer.finish().unwrap();
+
+            assert!(file.as_file().metadata().unwrap().len() <= bound);
+            assert_eq!(read_archive(format, file.path()), files);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::Write;
+
+use anyhow::Context;
+use zip::write::{ExtendedFileOptions, FileOptions};
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::archive::{compression_overhead, ArchiveWriter};
+
+/// Size of the zip end of central directory record.
+const END_OF_DIRECTORY_SIZE: u64 = 22;
+
+/// Zip archive, deflated or stored.
+pub struct ZipArchiveWriter {
+    zip: ZipWriter<File>,
+    method: CompressionMethod,
+}
+
+impl ZipArchiveWriter {
+    pub fn new(file: File, compress: bool) -> Self {
+        Self {
+            zip: ZipWriter::new(file),
+            method: if compress {
+                CompressionMethod::Deflated
+            } else {
+                CompressionMethod::Stored
+            },
+        }
+    }
+}
+
+impl ArchiveWriter for ZipArchiveWriter {
+    /// Covers the local header, the central directory record and extra fields.
+    fn entry_size(&self, name: &str, len: u64) -> u64 {
+        let headers = 30 + 46 + 2 * name.len() as u64 + 64;
+        match self.method {
+            CompressionMethod::Stored => headers + len,
+            _ => headers + len + compression_overhead(len),
+        }
+    }
+
+    fn trailer_size(&self) -> u64 {
+        END_OF_DIRECTORY_SIZE
+    }
+
+    fn add_file(&mut self, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        let options: FileOptions<ExtendedFileOptions> = FileOptions::default()
+            .compression_method(self.method)
+            .unix_permissions(0o755);
+
+        self.zip
+            .start_file(name, options)
+            .context("Failed to start file in zip archive")?;
+        self.zip
+            .write_all(data)
+            .context("Failed to write file to zip archive")?;
+
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        self.zip.finish().context("Failed to finish zip archive")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::{noise, read_archive};
+    use crate::archive::ArchiveFormat;
+
+    #[test]
+    fn round_trips_files() {
+        for (format, compress) in [
+            (ArchiveFormat::Zip, true),
+            (ArchiveFormat::ZipStored, false),
+        ] {
+            let files = [
+                ("01_😺.webp".to_string(), noise(5_000, 1)),
+                ("02.webp".to_string(), vec![0; 5_000]),
+                ("manifest.json".to_string(), b"{}".to_vec()),
+            ];
+
+            let file = tempfile::NamedTempFile::new().unwrap();
+            let mut writer = Box::new(ZipArchiveWriter::new(file.reopen().unwrap(), compress));
+            let mut bound = writer.trailer_size();
+            for (name, data) in &files {
+                bound += writer.entry_size(name, data.len() as u64);
+                writer.add_file(name, data).unwrap();
+            }
+            writer.finish().unwrap();
+
+            assert!(file.as_file().metadata().unwrap().len() <= bound);
+            assert_eq!(read_archive(format, file.path()), files);
+        }
+    }
+}
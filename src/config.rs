@@ -0,0 +1,672 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use governor::Quota;
+
+use crate::archive::DEFAULT_SPLIT_SIZE;
+use crate::convert::{AnimationOptions, OutputFormat};
+use crate::export::ExportOptions;
+use crate::observability::exporter::Exporter;
+use crate::storage::StorageBackend;
+use crate::webhook::{TlsFiles, WebhookConfig};
+
+/// Every setting with its default value, settings without one are optional,
+/// required or have a default computed from other settings.
+///
+/// A setting is read from the `<key>` entry of the config file, the `<KEY>` environment variable
+/// and the `--<key>` flag with dashes instead of underscores, in increasing order of precedence.
+const SETTINGS: &[(&str, Option<&str>)] = &[
+    ("teloxide_token", None),
+    ("telegram_api_url", Some("https://api.telegram.org")),
+    ("update_source", Some("polling")),
+    ("webhook_url", None),
+    ("webhook_address", Some("0.0.0.0:8443")),
+    ("webhook_path", None),
+    ("webhook_secret_token", None),
+    ("webhook_tls_cert", None),
+    ("webhook_tls_key", None),
+    ("webhook_tls_self_signed", Some("false")),
+    ("rate_limit", Some("20")),
+    ("rate_limit_burst", Some("5")),
+    ("group_rate_limit", Some("10")),
+    ("group_rate_limit_burst", Some("3")),
+    ("dialogue_storage", Some("sqlite:sticker-export-bot.sqlite")),
+    (
+        "settings_storage",
+        Some("sqlite:sticker-export-bot-settings.sqlite"),
+    ),
+    ("default_output_format", Some("gif")),
+    ("default_archive_format", Some("zip")),
+    ("pack_filename_template", Some("{index}_{emoji}")),
+    ("archive_split_size", None),
+    ("ffmpeg_path", Some("ffmpeg")),
+    ("ffmpeg_timeout", Some("60")),
+    ("conversion_workers", None),
+    ("conversion_workers_per_user", None),
+    ("pack_export_workers", Some("2")),
+    ("monitoring_address", Some("0.0.0.0:9090")),
+    ("otel_sdk_disabled", Some("false")),
+    ("otel_exporter", None),
+    ("otel_exporter_otlp_protocol", None),
+    ("otel_exporter_endpoint", None),
+    ("otel_exporter_file_path", Some("telemetry")),
+    ("otel_sample_rate", Some("1.0")),
+];
+
+/// Settings hidden by `--print-config`.
+const SECRETS: &[&str] = &["teloxide_token", "webhook_secret_token"];
+
+/// Config file read when neither `--config` nor `CONFIG_FILE` is given, if it exists.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// What the bot was asked to do on the command line.
+pub enum Command {
+    Run(Box<Config>),
+    /// Print the effective settings, along with the error that keeps them from being used.
+    PrintConfig(Values, Option<anyhow::Error>),
+    Help,
+}
+
+/// The validated settings of the bot.
+pub struct Config {
+    pub teloxide_token: String,
+    pub telegram_api_url: reqwest::Url,
+    /// Receive updates with a webhook instead of long polling.
+    pub webhook: Option<WebhookConfig>,
+    pub rate_limit: Quota,
+    pub group_rate_limit: Quota,
+    pub dialogue_storage: StorageBackend,
+    pub settings_storage: StorageBackend,
+    /// Export options of users who haven't changed their settings.
+    pub export_defaults: ExportOptions,
+    pub archive_split_size: u64,
+    pub ffmpeg_path: PathBuf,
+    pub ffmpeg_timeout: Duration,
+    pub conversion_workers: usize,
+    pub conversion_workers_per_user: usize,
+    /// Pack exports running at once, the others wait in the queue.
+    pub pack_export_workers: usize,
+    pub monitoring_address: SocketAddr,
+    pub telemetry_exporter: Exporter,
+    pub sample_rate: f64,
+}
+
+/// Where the value of a setting comes from.
+#[derive(Clone, Debug)]
+enum Source {
+    Default,
+    File(PathBuf),
+    Env,
+    Cli,
+}
+
+/// Raw values of the settings and their sources.
+#[derive(Default)]
+pub struct Values(BTreeMap<&'static str, (String, Source)>);
+
+impl Values {
+    fn set(&mut self, key: &'static str, value: String, source: Source) {
+        self.0.insert(key, (value, source));
+    }
+
+    /// How the user set `key`, for error messages.
+    fn describe(&self, key: &str) -> String {
+        match self.0.get(key).map(|(_, source)| source) {
+            Some(Source::File(path)) => format!("`{}` in {}", key, path.display()),
+            Some(Source::Env) => format!("`{}`", key.to_uppercase()),
+            Some(Source::Cli) => format!("`--{}`", key.replace('_', "-")),
+            Some(Source::Default) | None => format!("`{}`", key),
+        }
+    }
+
+    fn invalid(&self, key: &str, reason: impl Display) -> anyhow::Error {
+        match self.0.get(key) {
+            Some((value, _)) => {
+                anyhow::anyhow!("Invalid {} {:?}: {}", self.describe(key), value, reason)
+            }
+            None => anyhow::anyhow!("Invalid {}: {}", self.describe(key), reason),
+        }
+    }
+
+    /// Parse a setting, `None` if it isn't set.
+    fn get<T>(&self, key: &str) -> anyhow::Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.0
+            .get(key)
+            .map(|(value, _)| value.parse().map_err(|e| self.invalid(key, e)))
+            .transpose()
+    }
+
+    fn require<T>(&self, key: &str) -> anyhow::Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(key)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "`{}` is required, set `{}`, `--{}` or `{}` in the config file",
+                key,
+                key.to_uppercase(),
+                key.replace('_', "-"),
+                key
+            )
+        })
+    }
+
+    /// Parse a setting, or use and record a default computed from other settings.
+    fn get_or<T>(&mut self, key: &'static str, default: impl FnOnce() -> T) -> anyhow::Result<T>
+    where
+        T: FromStr + ToString,
+        T::Err: Display,
+    {
+        match self.get(key)? {
+            Some(value) => Ok(value),
+            None => {
+                let value = default();
+                self.set(key, value.to_string(), Source::Default);
+                Ok(value)
+            }
+        }
+    }
+}
+
+/// The key of a setting, `None` if there's no such setting.
+fn setting_key(name: &str) -> Option<&'static str> {
+    SETTINGS
+        .iter()
+        .map(|(key, _)| *key)
+        .find(|key| *key == name)
+}
+
+/// Read the command line, the environment and the config file.
+pub fn load() -> anyhow::Result<Command> {
+    let default_config = Some(Path::new(DEFAULT_CONFIG_FILE)).filter(|path| path.exists());
+    load_from(
+        std::env::args().skip(1),
+        |name| std::env::var(name).ok(),
+        default_config,
+    )
+}
+
+/// Read the given arguments, the environment variables returned by `env` and the config file,
+/// falling back to `default_config` when no config file is given.
+fn load_from(
+    mut args: impl Iterator<Item = String>,
+    env: impl Fn(&str) -> Option<String>,
+    default_config: Option<&Path>,
+) -> anyhow::Result<Command> {
+    let mut config_file = None;
+    let mut print_config = false;
+    let mut flags = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let flag = arg
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow::anyhow!("Unexpected argument `{}`, see `--help`", arg))?;
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+
+        match name {
+            "help" => return Ok(Command::Help),
+            "print-config" => print_config = true,
+            name => {
+                let key = match name {
+                    "config" => None,
+                    name => Some(setting_key(&name.replace('-', "_")).ok_or_else(|| {
+                        anyhow::anyhow!("Unknown flag `--{}`, see `--help`", name)
+                    })?),
+                };
+                let value = match value {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("`--{}` needs a value", name))?,
+                };
+
+                match key {
+                    Some(key) => flags.push((key, value)),
+                    None => config_file = Some(PathBuf::from(value)),
+                }
+            }
+        }
+    }
+
+    let mut values = Values::default();
+    for (key, default) in SETTINGS {
+        if let Some(default) = default {
+            values.set(key, default.to_string(), Source::Default);
+        }
+    }
+
+    let config_file = config_file
+        .or_else(|| env("CONFIG_FILE").map(PathBuf::from))
+        .or_else(|| default_config.map(Path::to_path_buf));
+    if let Some(path) = config_file {
+        read_file(&path, &mut values)?;
+    }
+    for (key, _) in SETTINGS {
+        if let Some(value) = env(&key.to_uppercase()) {
+            values.set(key, value, Source::Env);
+        }
+    }
+    for (key, value) in flags {
+        values.set(key, value, Source::Cli);
+    }
+
+    let config = Config::resolve(&mut values);
+    if print_config {
+        // also useful to find out why the config is invalid, e.g. which secret is missing
+        return Ok(Command::PrintConfig(values, config.err()));
+    }
+
+    Ok(Command::Run(Box::new(config?)))
+}
+
+/// Read the settings of a TOML config file.
+fn read_file(path: &Path, values: &mut Values) -> anyhow::Result<()> {
+    let table = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .parse::<toml::Table>()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    for (name, value) in table {
+        let key = setting_key(&name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown setting `{}` in {}", name, path.display()))?;
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                value.to_string()
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "`{}` in {} must be a string, a number or a boolean",
+                    key,
+                    path.display()
+                ))
+            }
+        };
+
+        values.set(key, value, Source::File(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// Validate the settings, recording the defaults computed from other settings in `values`.
+    fn resolve(values: &mut Values) -> anyhow::Result<Self> {
+        let conversion_workers = values.get_or("conversion_workers", || {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        })?;
+        if conversion_workers == 0 {
+            return Err(values.invalid("conversion_workers", "must be at least 1"));
+        }
+        let conversion_workers_per_user = values.get_or("conversion_workers_per_user", || {
+            (conversion_workers / 2).max(1)
+        })?;
+        if conversion_workers_per_user == 0 {
+            return Err(values.invalid("conversion_workers_per_user", "must be at least 1"));
+        }
+
+        let pack_export_workers = values.require("pack_export_workers")?;
+        if pack_export_workers == 0 {
+            return Err(values.invalid("pack_export_workers", "must be at least 1"));
+        }
+
+        let archive_split_size = values.get_or("archive_split_size", || DEFAULT_SPLIT_SIZE)?;
+        if archive_split_size == 0 {
+            return Err(values.invalid("archive_split_size", "must be at least 1"));
+        }
+        let ffmpeg_timeout = values.require::<u64>("ffmpeg_timeout")?;
+        if ffmpeg_timeout == 0 {
+            return Err(values.invalid("ffmpeg_timeout", "must be at least 1 second"));
+        }
+        let sample_rate = values.require::<f64>("otel_sample_rate")?;
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err(values.invalid("otel_sample_rate", "must be between 0 and 1"));
+        }
+
+        let telemetry_exporter = Self::telemetry_exporter(values)?;
+
+        // the secrets come last, so that the computed defaults are recorded without them
+        let webhook = match values.require::<String>("update_source")?.as_str() {
+            "polling" => None,
+            "webhook" => Some(Self::webhook(values)?),
+            _ => return Err(values.invalid("update_source", "expected `polling` or `webhook`")),
+        };
+
+        Ok(Self {
+            teloxide_token: values.require("teloxide_token")?,
+            telegram_api_url: values.require("telegram_api_url")?,
+            webhook,
+            rate_limit: Quota::per_minute(values.require::<NonZeroU32>("rate_limit")?)
+                .allow_burst(values.require("rate_limit_burst")?),
+            group_rate_limit: Quota::per_minute(values.require::<NonZeroU32>("group_rate_limit")?)
+                .allow_burst(values.require("group_rate_limit_burst")?),
+            dialogue_storage: values.require("dialogue_storage")?,
+            settings_storage: values.require("settings_storage")?,
+            export_defaults: ExportOptions {
+                format: values.require::<OutputFormat>("default_output_format")?,
+                archive: values.require("default_archive_format")?,
+                filename_template: values.require("pack_filename_template")?,
+                animation: AnimationOptions::default(),
+                manifest: true,
+            },
+            archive_split_size,
+            ffmpeg_path: values.require("ffmpeg_path")?,
+            ffmpeg_timeout: Duration::from_secs(ffmpeg_timeout),
+            conversion_workers,
+            conversion_workers_per_user,
+            pack_export_workers,
+            monitoring_address: values.require("monitoring_address")?,
+            telemetry_exporter,
+            sample_rate,
+        })
+    }
+
+    fn webhook(values: &Values) -> anyhow::Result<WebhookConfig> {
+        let secret_token = values.get::<String>("webhook_secret_token")?;
+        // Telegram rejects other tokens when setting the webhook
+        if secret_token.as_ref().is_some_and(|token| {
+            token.is_empty()
+                || token.len() > 256
+                || !token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        }) {
+            return Err(values.invalid(
+                "webhook_secret_token",
+                "must be 1-256 characters of A-Z, a-z, 0-9, _ and -",
+            ));
+        }
+
+        let tls = match (
+            values.get("webhook_tls_cert")?,
+            values.get("webhook_tls_key")?,
+        ) {
+            (Some(certificate), Some(key)) => Some(TlsFiles {
+                certificate,
+                key,
+                self_signed: values.require("webhook_tls_self_signed")?,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "`webhook_tls_cert` and `webhook_tls_key` must be set together"
+                ))
+            }
+        };
+
+        Ok(WebhookConfig {
+            url: values.require("webhook_url")?,
+            address: values.require("webhook_address")?,
+            path: values.get("webhook_path")?,
+            secret_token,
+            tls,
+        })
+    }
+
+    fn telemetry_exporter(values: &mut Values) -> anyhow::Result<Exporter> {
+        if values.require::<bool>("otel_sdk_disabled")? {
+            return Ok(Exporter::None);
+        }
+
+        let http = values
+            .get::<String>("otel_exporter_otlp_protocol")?
+            .is_some_and(|protocol| protocol == "http/protobuf");
+        let name = values.get_or("otel_exporter", || {
+            if http { "otlp_http" } else { "otlp_grpc" }.to_string()
+        })?;
+        Ok(match name.as_str() {
+            "none" => Exporter::None,
+            "stdout" => Exporter::Stdout,
+            "file" => Exporter::File(values.require("otel_exporter_file_path")?),
+            "otlp_grpc" => Exporter::OtlpGrpc(values.get_or("otel_exporter_endpoint", || {
+                "http://localhost:4317".to_string()
+            })?),
+            "otlp_http" => Exporter::OtlpHttp(values.get_or("otel_exporter_endpoint", || {
+                "http://localhost:4318".to_string()
+            })?),
+            _ => {
+                return Err(values.invalid(
+                    "otel_exporter",
+                    "expected `otlp_grpc`, `otlp_http`, `stdout`, `file` or `none`",
+                ))
+            }
+        })
+    }
+}
+
+/// The effective settings as a config file, with secrets hidden.
+impl Display for Values {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (key, _) in SETTINGS {
+            match self.0.get(key) {
+                Some((value, source)) => {
+                    let value = if SECRETS.contains(key) {
+                        "<hidden>"
+                    } else {
+                        value.as_str()
+                    };
+                    let source = match source {
+                        Source::Default => "default".to_string(),
+                        Source::File(path) => path.display().to_string(),
+                        Source::Env => format!("`{}`", key.to_uppercase()),
+                        Source::Cli => format!("`--{}`", key.replace('_', "-")),
+                    };
+                    // numbers and booleans are read back the same either way
+                    let value = if value.parse::<i64>().is_ok() || value.parse::<bool>().is_ok() {
+                        value.to_string()
+                    } else {
+                        toml::Value::String(value.to_string()).to_string()
+                    };
+                    writeln!(f, "{} = {} # {}", key, value, source)?;
+                }
+                None => writeln!(f, "# {} is not set", key)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Usage shown by `--help`.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: sticker-export-bot [--config <file>] [--print-config] [--<setting> <value>]...\n\n\
+         Settings are read from the config file (default: config.toml), the environment and\n\
+         the command line, in increasing order of precedence:\n\n",
+    );
+    for (key, default) in SETTINGS {
+        usage.push_str(&format!(
+            "  --{:<30} {}",
+            key.replace('_', "-"),
+            key.to_uppercase()
+        ));
+        if let Some(default) = default {
+            usage.push_str(&format!(" (default: {})", default));
+        }
+        usage.push('\n');
+    }
+
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn config_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn load_with(args: &[&str], env: &[(&str, &str)]) -> anyhow::Result<Command> {
+        load_with_default(args, env, None)
+    }
+
+    fn load_with_default(
+        args: &[&str],
+        env: &[(&str, &str)],
+        default_config: Option<&Path>,
+    ) -> anyhow::Result<Command> {
+        let env = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        load_from(
+            args.iter().map(|arg| arg.to_string()),
+            |name| env.get(name).cloned(),
+            default_config,
+        )
+    }
+
+    fn run_config(command: anyhow::Result<Command>) -> Config {
+        match command.unwrap() {
+            Command::Run(config) => *config,
+            _ => panic!("Expected a config to run with"),
+        }
+    }
+
+    #[test]
+    fn flags_override_environment_overrides_file() {
+        let file = config_file(
+            r#"
+            teloxide_token = "file-token"
+            pack_export_workers = 1
+            conversion_workers = 4
+            ffmpeg_timeout = 6
+            "#,
+        );
+        let path = file.path().to_str().unwrap();
+
+        let config = run_config(load_with(
+            &["--config", path, "--pack-export-workers", "3"],
+            &[("PACK_EXPORT_WORKERS", "2"), ("CONVERSION_WORKERS", "5")],
+        ));
+        assert_eq!(config.teloxide_token, "file-token");
+        assert_eq!(config.pack_export_workers, 3);
+        assert_eq!(config.conversion_workers, 5);
+        assert_eq!(config.ffmpeg_timeout, Duration::from_secs(6));
+        // untouched settings keep their defaults, computed ones follow the others
+        assert_eq!(config.archive_split_size, DEFAULT_SPLIT_SIZE);
+        assert_eq!(config.conversion_workers_per_user, 2);
+    }
+
+    #[test]
+    fn config_file_flag_overrides_environment() {
+        let env_file = config_file("teloxide_token = \"env-file\"");
+        let flag_file = config_file("teloxide_token = \"flag-file\"");
+        let env = [("CONFIG_FILE", env_file.path().to_str().unwrap())];
+
+        let config = run_config(load_with(&[], &env));
+        assert_eq!(config.teloxide_token, "env-file");
+
+        let flag = format!("--config={}", flag_file.path().display());
+        let config = run_config(load_with(&[&flag], &env));
+        assert_eq!(config.teloxide_token, "flag-file");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_config_file() {
+        let default = config_file("teloxide_token = \"default\"");
+        let other = config_file("teloxide_token = \"other\"");
+        let other = other.path().to_str().unwrap();
+
+        let config = run_config(load_with_default(&[], &[], Some(default.path())));
+        assert_eq!(config.teloxide_token, "default");
+
+        let config = run_config(load_with_default(
+            &[],
+            &[("CONFIG_FILE", other)],
+            Some(default.path()),
+        ));
+        assert_eq!(config.teloxide_token, "other");
+
+        let config = run_config(load_with_default(
+            &["--config", other],
+            &[],
+            Some(default.path()),
+        ));
+        assert_eq!(config.teloxide_token, "other");
+    }
+
+    #[test]
+    fn prints_config_without_required_settings() {
+        let command = load_with(
+            &["--print-config", "--webhook-secret-token", "secret"],
+            &[("CONFIG_FILE", "/nonexistent/config.toml")],
+        );
+        // the config file named by the environment has to exist
+        assert!(command.is_err());
+
+        let (values, error) = match load_with(
+            &["--print-config", "--webhook-secret-token", "secret"],
+            &[("RATE_LIMIT", "7")],
+        )
+        .unwrap()
+        {
+            Command::PrintConfig(values, error) => (values, error),
+            _ => panic!("Expected the config to be printed"),
+        };
+        assert!(error
+            .unwrap()
+            .to_string()
+            .contains("`teloxide_token` is required"));
+
+        let printed = values.to_string();
+        assert!(printed.contains("# teloxide_token is not set\n"));
+        assert!(printed.contains("rate_limit = 7 # `RATE_LIMIT`\n"));
+        assert!(
+            printed.contains("webhook_secret_token = \"<hidden>\" # `--webhook-secret-token`\n")
+        );
+        assert!(!printed.contains("secret\""));
+        // computed defaults are printed too
+        assert!(printed.contains("otel_exporter = \"otlp_grpc\" # default\n"));
+    }
+
+    #[test]
+    fn reports_invalid_settings_with_their_source() {
+        let error = |args: &[&str], env: &[(&str, &str)]| match load_with(args, env) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("Expected {:?} {:?} to be rejected", args, env),
+        };
+
+        assert_eq!(
+            error(&["--rate-limit", "abc"], &[("TELOXIDE_TOKEN", "token")]),
+            "Invalid `--rate-limit` \"abc\": invalid digit found in string"
+        );
+        assert!(error(
+            &[],
+            &[("TELOXIDE_TOKEN", "token"), ("OTEL_SAMPLE_RATE", "2")]
+        )
+        .starts_with("Invalid `OTEL_SAMPLE_RATE` \"2\""));
+        assert!(error(&["--no-such-setting", "1"], &[]).starts_with("Unknown flag"));
+        assert!(error(&["--rate-limit"], &[]).contains("needs a value"));
+        assert!(error(&["positional"], &[]).starts_with("Unexpected argument"));
+
+        let file = config_file("no_such_setting = 1");
+        let path = file.path().to_str().unwrap();
+        assert!(error(&["--config", path], &[]).starts_with("Unknown setting `no_such_setting`"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageError, RgbaImage};
+
+use crate::convert::Canceled;
+
+/// NeuQuant speed of GIF quantization, 1 is the slowest and 30 the fastest.
+const GIF_SPEED: i32 = 10;
+
+/// Encode frames as a looping GIF, stopping between frames once `canceled`.
+pub fn encode_gif(
+    frames: Vec<RgbaImage>,
+    fps: u32,
+    canceled: &Canceled,
+) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(Cursor::new(&mut buf), GIF_SPEED);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .context("Failed to set GIF repeat")?;
+        encoder
+            .try_encode_frames(frames.into_iter().map(|frame| {
+                canceled.check().map_err(|e| {
+                    ImageError::IoError(std::io::Error::new(std::io::ErrorKind::Interrupted, e))
+                })?;
+                Ok(Frame::from_parts(
+                    frame,
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(1000, fps),
+                ))
+            }))
+            .context("Failed to encode GIF")?;
+    }
+
+    Ok(buf)
+}
+
+/// Encode frames as a looping APNG with full alpha, stopping between frames once `canceled`.
+pub fn encode_apng(frames: &[RgbaImage], fps: u32, canceled: &Canceled) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = frames[0].dimensions();
+
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(Cursor::new(&mut buf), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frames.len() as u32, 0)
+            .context("Failed to set APNG animation")?;
+        encoder
+            .set_frame_delay(1, fps as u16)
+            .context("Failed to set APNG frame delay")?;
+
+        let mut writer = encoder
+            .write_header()
+            .context("Failed to write APNG header")?;
+        for frame in frames {
+            canceled.check()?;
+            writer
+                .write_image_data(frame.as_raw())
+                .context("Failed to write APNG frame")?;
+        }
+        writer.finish().context("Failed to finish APNG")?;
+    }
+
+    Ok(buf)
+}
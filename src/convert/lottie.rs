@@ -0,0 +1,181 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use image::RgbaImage;
+use rlottie::{Animation, Size, Surface};
+use zip::ZipWriter;
+
+use crate::convert::transcoder::Transcoder;
+use crate::convert::{
+    animation, spawn_blocking, still, video, AnimationOptions, Canceled, OutputFormat,
+};
+
+/// Decompress a TGS animated sticker into its Lottie JSON document.
+#[tracing::instrument(skip(tgs))]
+pub fn decompress_tgs(tgs: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut json = Vec::new();
+    GzDecoder::new(tgs)
+        .read_to_end(&mut json)
+        .context("Failed to decompress TGS file")?;
+
+    Ok(json)
+}
+
+/// Package a Lottie JSON document as a [dotLottie](https://dotlottie.io) archive.
+///
+/// The archive contains a single animation named after `id` and a manifest
+/// marking it as the active, looping animation.
+#[tracing::instrument(skip(json))]
+pub fn package_dotlottie(json: &[u8], id: &str) -> anyhow::Result<Vec<u8>> {
+    let manifest = serde_json::json!({
+        "version": "1",
+        "generator": concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+        "author": env!("CARGO_PKG_NAME"),
+        "activeAnimationId": id,
+        "animations": [{
+            "id": id,
+            "speed": 1,
+            "loop": true,
+            "direction": 1,
+            "playMode": "normal",
+        }],
+    });
+
+    let mut buf = Vec::new();
+    {
+        let mut zip = ZipWriter::new(Cursor::new(&mut buf));
+        let options: zip::write::FileOptions<zip::write::ExtendedFileOptions> =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        zip.start_file("manifest.json", options.clone())
+            .context("Failed to start manifest in dotLottie archive")?;
+        serde_json::to_writer(&mut zip, &manifest)
+            .context("Failed to write manifest to dotLottie archive")?;
+
+        zip.start_file(format!("animations/{}.json", id), options)
+            .context("Failed to start animation in dotLottie archive")?;
+        zip.write_all(json)
+            .context("Failed to write animation to dotLottie archive")?;
+
+        zip.finish().context("Failed to finish dotLottie archive")?;
+    }
+
+    Ok(buf)
+}
+
+/// Convert a TGS animated sticker to the given output format.
+///
+/// Still formats take the first frame of the animation.
+#[tracing::instrument(skip(transcoder, tgs))]
+pub async fn convert_tgs(
+    transcoder: &Transcoder,
+    tgs: &[u8],
+    format: OutputFormat,
+    options: AnimationOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let json = decompress_tgs(tgs)?;
+    let first_frame_only = matches!(format, OutputFormat::Png | OutputFormat::Webp);
+
+    // rendering is CPU bound, keep it away from the async workers
+    let frames =
+        spawn_blocking(move |canceled| render_lottie(json, options, first_frame_only, canceled))
+            .await
+            .context("Lottie render task panicked")??;
+
+    match format {
+        OutputFormat::AnimatedWebp | OutputFormat::Mp4 => {
+            video::encode_frames(transcoder, &frames, format, options.fps).await
+        }
+        // encoding is CPU bound as well
+        _ => spawn_blocking(move |canceled| match format {
+            OutputFormat::Png => still::encode_png(&frames[0]),
+            OutputFormat::Webp => still::encode_webp(&frames[0]),
+            OutputFormat::Gif => animation::encode_gif(frames, options.fps, canceled),
+            OutputFormat::Apng => animation::encode_apng(&frames, options.fps, canceled),
+            _ => Err(anyhow::anyhow!(
+                "Can't convert animated sticker to {}",
+                format
+            )),
+        })
+        .await
+        .context("Encode task panicked")?,
+    }
+}
+
+/// Render a Lottie JSON document to RGBA frames with rlottie.
+///
+/// The animation is scaled and resampled according to `options`,
+/// only the first frame is rendered if `first_frame_only` is set.
+fn render_lottie(
+    json: Vec<u8>,
+    options: AnimationOptions,
+    first_frame_only: bool,
+    canceled: &Canceled,
+) -> anyhow::Result<Vec<RgbaImage>> {
+    // an empty cache key disables rlottie's internal animation cache
+    let mut animation =
+        Animation::from_data(json, "", "").context("Failed to parse Lottie animation")?;
+
+    let source_size = animation.size();
+    if source_size.width == 0 || source_size.height == 0 {
+        return Err(anyhow::anyhow!("Lottie animation has an empty canvas"));
+    }
+
+    let width = options.width as usize;
+    let height = ((source_size.height * width) as f64 / source_size.width as f64)
+        .round()
+        .max(1.0) as usize;
+
+    let total_frames = animation.totalframe();
+    let frame_rate = animation.framerate();
+    if total_frames == 0 || frame_rate <= 0.0 {
+        return Err(anyhow::anyhow!("Lottie animation has no frames"));
+    }
+
+    // resample the animation to the output frame rate
+    let duration = total_frames as f64 / frame_rate;
+    let output_frames = if first_frame_only {
+        1
+    } else {
+        ((duration * options.fps as f64).round() as usize).max(1)
+    };
+
+    let mut surface = Surface::new(Size::new(width, height));
+    let mut frames = Vec::with_capacity(output_frames);
+
+    for i in 0..output_frames {
+        canceled.check()?;
+        let source_frame =
+            ((i as f64 / options.fps as f64 * frame_rate) as usize).min(total_frames - 1);
+        animation.render(source_frame, &mut surface);
+        frames.push(surface_to_image(&surface)?);
+    }
+
+    Ok(frames)
+}
+
+/// Convert a rendered surface (premultiplied BGRA) into a straight-alpha RGBA image.
+fn surface_to_image(surface: &Surface) -> anyhow::Result<RgbaImage> {
+    let mut pixels = Vec::with_capacity(surface.width() * surface.height() * 4);
+
+    for pixel in surface.data() {
+        let unpremultiply = |c: u8| -> u8 {
+            if pixel.a == 0 {
+                0
+            } else {
+                ((c as u32 * 255 + pixel.a as u32 / 2) / pixel.a as u32).min(255) as u8
+            }
+        };
+
+        pixels.extend_from_slice(&[
+            unpremultiply(pixel.r),
+            unpremultiply(pixel.g),
+            unpremultiply(pixel.b),
+            pixel.a,
+        ]);
+    }
+
+    RgbaImage::from_raw(surface.width() as u32, surface.height() as u32, pixels)
+        .context("Rendered surface has an unexpected size")
+}
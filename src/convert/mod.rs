@@ -0,0 +1,263 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context;
+use infer::Infer;
+use serde::{Deserialize, Serialize};
+use teloxide::types::StickerFormat;
+use tokio::task::JoinError;
+
+use crate::convert::transcoder::Transcoder;
+use crate::observability::metrics::metrics;
+
+pub(crate) mod animation;
+pub(crate) mod lottie;
+#[cfg(feature = "native-video")]
+pub(crate) mod native;
+pub(crate) mod still;
+pub(crate) mod transcoder;
+pub(crate) mod video;
+#[cfg(feature = "native-video")]
+pub(crate) mod vpx;
+// the demuxer is tested without libvpx
+#[cfg(any(feature = "native-video", test))]
+pub(crate) mod webm;
+
+/// Frame rate and size of converted animations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationOptions {
+    /// Frames per second.
+    pub fps: u32,
+    /// Width in pixels, the height is scaled to keep the aspect ratio.
+    pub width: u32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            fps: 30,
+            width: 320,
+        }
+    }
+}
+
+/// Output format requested by the user.
+///
+/// Still formats (PNG, WebP) export the first frame of animated and video stickers,
+/// animated formats export static stickers as their still counterpart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Png,
+    Webp,
+    #[default]
+    Gif,
+    Apng,
+    AnimatedWebp,
+    Mp4,
+    /// The file as stored by Telegram (`.webp`, `.tgs` or `.webm`).
+    Original,
+    /// Plain Lottie JSON for animated stickers.
+    Lottie,
+    /// dotLottie (`.lottie`) archive for animated stickers.
+    DotLottie,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 9] = [
+        OutputFormat::Png,
+        OutputFormat::Webp,
+        OutputFormat::Gif,
+        OutputFormat::Apng,
+        OutputFormat::AnimatedWebp,
+        OutputFormat::Mp4,
+        OutputFormat::Original,
+        OutputFormat::Lottie,
+        OutputFormat::DotLottie,
+    ];
+
+    /// The identifier used in command arguments and callback data.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Apng => "apng",
+            OutputFormat::AnimatedWebp => "awebp",
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Original => "original",
+            OutputFormat::Lottie => "lottie",
+            OutputFormat::DotLottie => "dotlottie",
+        }
+    }
+
+    /// Resolve the format actually produced for a sticker of the given format.
+    pub fn resolve(&self, sticker_format: &StickerFormat) -> OutputFormat {
+        match (sticker_format, self) {
+            (_, OutputFormat::Original) => OutputFormat::Original,
+            (StickerFormat::Raster, OutputFormat::Webp | OutputFormat::AnimatedWebp) => {
+                OutputFormat::Webp
+            }
+            (StickerFormat::Raster, _) => OutputFormat::Png,
+            (StickerFormat::Video, OutputFormat::Lottie | OutputFormat::DotLottie) => {
+                OutputFormat::Gif
+            }
+            (_, format) => *format,
+        }
+    }
+
+    /// File extension of a resolved format.
+    pub fn extension(&self, sticker_format: &StickerFormat) -> &'static str {
+        match self {
+            OutputFormat::Png | OutputFormat::Apng => "png",
+            OutputFormat::Webp | OutputFormat::AnimatedWebp => "webp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Lottie => "json",
+            OutputFormat::DotLottie => "lottie",
+            OutputFormat::Original => match sticker_format {
+                StickerFormat::Raster => "webp",
+                StickerFormat::Animated => "tgs",
+                StickerFormat::Video => "webm",
+            },
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Png => "PNG",
+            OutputFormat::Webp => "WebP",
+            OutputFormat::Gif => "GIF",
+            OutputFormat::Apng => "APNG",
+            OutputFormat::AnimatedWebp => "Animated WebP",
+            OutputFormat::Mp4 => "MP4",
+            OutputFormat::Original => "Original",
+            OutputFormat::Lottie => "Lottie JSON",
+            OutputFormat::DotLottie => "dotLottie",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "webp" => Ok(OutputFormat::Webp),
+            "gif" => Ok(OutputFormat::Gif),
+            "apng" => Ok(OutputFormat::Apng),
+            "awebp" | "animated_webp" | "webp_anim" => Ok(OutputFormat::AnimatedWebp),
+            "mp4" => Ok(OutputFormat::Mp4),
+            "original" | "raw" => Ok(OutputFormat::Original),
+            "lottie" | "json" => Ok(OutputFormat::Lottie),
+            "dotlottie" | ".lottie" => Ok(OutputFormat::DotLottie),
+            _ => Err(anyhow::anyhow!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Set once the conversion running on a blocking thread is no longer awaited.
+#[derive(Clone, Debug, Default)]
+pub struct Canceled(Arc<AtomicBool>);
+
+impl Canceled {
+    /// Fail if the conversion was canceled, checked between frames.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.0.load(Ordering::Relaxed) {
+            return Err(anyhow::anyhow!("Conversion canceled"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Cancels the conversion when dropped along with the future awaiting it.
+struct CancelOnDrop(Canceled);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0 .0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run a CPU bound conversion on the blocking thread pool.
+///
+/// Aborting a task doesn't stop blocking work, so the conversion gets a flag that is set
+/// once the returned future is dropped, e.g. when its export is canceled, and stops early.
+pub async fn spawn_blocking<T, F>(conversion: F) -> Result<T, JoinError>
+where
+    F: FnOnce(&Canceled) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let canceled = Canceled::default();
+    let _cancel = CancelOnDrop(canceled.clone());
+
+    tokio::task::spawn_blocking(move || conversion(&canceled)).await
+}
+
+/// Detect the sticker format from the file content.
+#[tracing::instrument(skip(data))]
+pub fn detect_sticker_format(data: &[u8]) -> anyhow::Result<StickerFormat> {
+    let infer = Infer::new();
+    let kind = infer.get(data).context("Failed to infer file type")?;
+
+    let mime = kind.mime_type();
+    match mime.split('/').next().unwrap_or_default() {
+        // animated stickers are gzip-compressed lottie animations (tgs)
+        "application" if mime == "application/gzip" => Ok(StickerFormat::Animated),
+        "image" => Ok(StickerFormat::Raster),
+        "video" => Ok(StickerFormat::Video),
+        _ => Err(anyhow::anyhow!("Unsupported file type")),
+    }
+}
+
+/// Convert a sticker file to the requested output format.
+///
+/// `id` names the animation inside dotLottie archives, `animation` applies to animated
+/// output formats. Returns the file extension together with the converted data.
+#[tracing::instrument(skip(transcoder, data))]
+pub async fn convert_sticker(
+    transcoder: &Transcoder,
+    data: Vec<u8>,
+    id: &str,
+    format: OutputFormat,
+    animation: AnimationOptions,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    let sticker_format = detect_sticker_format(&data)?;
+    let target = format.resolve(&sticker_format);
+    let extension = target.extension(&sticker_format);
+    let start = Instant::now();
+
+    let data = match (&sticker_format, target) {
+        (_, OutputFormat::Original) => data,
+        (StickerFormat::Raster, OutputFormat::Webp) => {
+            still::convert_unknown_image_to_webp(&data).context("Failed to convert image")?
+        }
+        (StickerFormat::Raster, _) => {
+            still::convert_unknown_image_to_png(&data).context("Failed to convert image")?
+        }
+        (StickerFormat::Animated, OutputFormat::Lottie) => lottie::decompress_tgs(&data)?,
+        (StickerFormat::Animated, OutputFormat::DotLottie) => {
+            let json = lottie::decompress_tgs(&data)?;
+            lottie::package_dotlottie(&json, id).context("Failed to package animated sticker")?
+        }
+        (StickerFormat::Animated, target) => {
+            lottie::convert_tgs(transcoder, &data, target, animation)
+                .await
+                .context("Failed to convert animated sticker")?
+        }
+        (StickerFormat::Video, target) => video::convert_webm(transcoder, &data, target, animation)
+            .await
+            .context("Failed to convert video")?,
+    };
+
+    metrics().record_conversion(&sticker_format, target, start.elapsed());
+
+    Ok((extension, data))
+}
@@ -0,0 +1,139 @@
+//! In-process video sticker conversion, without temporary files or ffmpeg.
+//!
+//! The WebM container is demuxed in Rust, VP9 is decoded by libvpx through FFI.
+//! Only still and GIF/APNG output is encoded here, see [`supports`].
+
+use anyhow::Context;
+use image::imageops::FilterType;
+use image::{GrayImage, RgbaImage};
+
+use crate::convert::vpx::Vp9Decoder;
+use crate::convert::{
+    animation, spawn_blocking, still, webm, AnimationOptions, Canceled, OutputFormat,
+};
+
+/// Whether the format can be produced without ffmpeg.
+pub fn supports(format: OutputFormat) -> bool {
+    matches!(
+        format,
+        OutputFormat::Png | OutputFormat::Webp | OutputFormat::Gif | OutputFormat::Apng
+    )
+}
+
+/// Convert a webm video to the given output format in-process.
+#[tracing::instrument(skip(video))]
+pub async fn convert_webm(
+    video: &[u8],
+    format: OutputFormat,
+    options: AnimationOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let video = video.to_vec();
+
+    // demuxing, decoding and encoding are all CPU bound
+    spawn_blocking(move |canceled| {
+        let first_frame_only = matches!(format, OutputFormat::Png | OutputFormat::Webp);
+        let frames = decode_webm(&video, options, first_frame_only, canceled)?;
+
+        match format {
+            OutputFormat::Png => still::encode_png(&frames[0]),
+            OutputFormat::Webp => still::encode_webp(&frames[0]),
+            OutputFormat::Gif => animation::encode_gif(frames, options.fps, canceled),
+            OutputFormat::Apng => animation::encode_apng(&frames, options.fps, canceled),
+            _ => Err(anyhow::anyhow!(
+                "Can't convert video in-process to {}",
+                format
+            )),
+        }
+    })
+    .await
+    .context("Video conversion task panicked")?
+}
+
+/// Decode a VP9 webm video to RGBA frames, keeping its alpha stream.
+///
+/// The video is scaled and resampled according to `options`,
+/// only the first frame is decoded if `first_frame_only` is set.
+fn decode_webm(
+    video: &[u8],
+    options: AnimationOptions,
+    first_frame_only: bool,
+    canceled: &Canceled,
+) -> anyhow::Result<Vec<RgbaImage>> {
+    let video = webm::demux(video).context("Failed to demux video")?;
+    if video.codec_id != "V_VP9" {
+        return Err(anyhow::anyhow!(
+            "Unsupported video codec {}",
+            video.codec_id
+        ));
+    }
+
+    let last = video.frames.last().context("Video has no frames")?;
+    // the last frame is shown for as long as the average frame
+    let duration = if video.frames.len() > 1 {
+        last.timestamp * video.frames.len() as f64 / (video.frames.len() - 1) as f64
+    } else {
+        1.0 / options.fps as f64
+    };
+    let output_frames = ((duration * options.fps as f64).round() as usize).max(1);
+
+    let mut decoder = Vp9Decoder::new()?;
+    let mut alpha_decoder = None;
+    let mut current: Option<RgbaImage> = None;
+    let mut frames = Vec::with_capacity(output_frames);
+
+    for frame in &video.frames {
+        canceled.check()?;
+        // every packet has to be decoded, later frames reference earlier ones
+        let mut image = match decoder.decode_rgba(&frame.data)? {
+            Some(image) => image,
+            None => continue,
+        };
+
+        if let Some(alpha) = &frame.alpha {
+            let alpha_decoder = match &mut alpha_decoder {
+                Some(decoder) => decoder,
+                None => alpha_decoder.insert(Vp9Decoder::new()?),
+            };
+
+            if let Some(alpha) = alpha_decoder.decode_luma(alpha)? {
+                let alpha = GrayImage::from_raw(image.width(), image.height(), alpha)
+                    .context("Alpha stream size doesn't match the video")?;
+                for (pixel, alpha) in image.pixels_mut().zip(alpha.pixels()) {
+                    pixel.0[3] = alpha.0[0];
+                }
+            }
+        }
+
+        let image = scale(&image, options.width);
+        if first_frame_only {
+            return Ok(vec![image]);
+        }
+
+        // emit the output frames shown before this frame, the first frame covers the start
+        while frames.len() < output_frames
+            && (frames.len() as f64 / options.fps as f64) < frame.timestamp
+        {
+            frames.push(current.as_ref().unwrap_or(&image).clone());
+        }
+        current = Some(image);
+
+        if frames.len() == output_frames {
+            break;
+        }
+    }
+
+    let current = current.context("Video has no decodable frames")?;
+    while frames.len() < output_frames {
+        frames.push(current.clone());
+    }
+
+    Ok(frames)
+}
+
+fn scale(image: &RgbaImage, width: u32) -> RgbaImage {
+    let height = ((image.height() * width) as f64 / image.width() as f64)
+        .round()
+        .max(1.0) as u32;
+
+    image::imageops::resize(image, width, height, FilterType::Lanczos3)
+}
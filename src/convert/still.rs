@@ -0,0 +1,55 @@
+use std::io::Cursor;
+
+use anyhow::Context;
+use image::codecs::webp::WebPEncoder;
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+/// Convert an unknown image to PNG format.
+#[tracing::instrument(skip(image))]
+pub fn convert_unknown_image_to_png(image: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let img = decode_unknown_image(image)?;
+
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .context("Failed to encode image")?;
+
+    Ok(buf)
+}
+
+/// Convert an unknown image to WebP format, WebP input is passed through untouched.
+#[tracing::instrument(skip(image))]
+pub fn convert_unknown_image_to_webp(image: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if infer::image::is_webp(image) {
+        return Ok(image.to_vec());
+    }
+
+    encode_webp(&decode_unknown_image(image)?.into_rgba8())
+}
+
+/// Encode a frame as PNG.
+pub fn encode_png(frame: &RgbaImage) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    frame
+        .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .context("Failed to encode PNG")?;
+
+    Ok(buf)
+}
+
+/// Encode a frame as lossless WebP, keeping the alpha channel.
+pub fn encode_webp(frame: &RgbaImage) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    frame
+        .write_with_encoder(WebPEncoder::new_lossless(Cursor::new(&mut buf)))
+        .context("Failed to encode WebP")?;
+
+    Ok(buf)
+}
+
+fn decode_unknown_image(image: &[u8]) -> anyhow::Result<DynamicImage> {
+    ImageReader::new(Cursor::new(image))
+        .with_guessed_format()?
+        .decode()
+        .context("Failed to decode image")
+}
@@ -0,0 +1,80 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::process::Command;
+
+/// Maximum length of the ffmpeg stderr tail recorded on spans and errors.
+const STDERR_TAIL_LEN: usize = 2048;
+
+/// Runs ffmpeg jobs as async child processes.
+///
+/// Every job is bounded by a timeout, and the child process is killed as soon as
+/// the job future is dropped, so cancelled requests don't leave ffmpeg running.
+#[derive(Debug)]
+pub struct Transcoder {
+    ffmpeg: PathBuf,
+    timeout: Duration,
+}
+
+impl Transcoder {
+    pub fn new(ffmpeg: impl Into<PathBuf>, timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            ffmpeg: ffmpeg.into(),
+            timeout,
+        })
+    }
+
+    /// Run ffmpeg with the given arguments and wait for it to exit successfully.
+    #[tracing::instrument(skip_all, fields(ffmpeg = ?self.ffmpeg, status, stderr))]
+    pub async fn run<I, S>(&self, args: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let child = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-nostdin", "-loglevel", "warning", "-y"])
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start ffmpeg")?;
+
+        // the child is killed when the timed out future is dropped
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| anyhow::anyhow!("ffmpeg timed out after {:?}", self.timeout))?
+            .context("Failed to wait for ffmpeg")?;
+
+        let stderr = stderr_tail(&output.stderr);
+        let span = tracing::Span::current();
+        span.record("status", tracing::field::debug(&output.status));
+        span.record("stderr", stderr.as_str());
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                stderr
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep the end of ffmpeg's stderr, where the actual error is reported.
+fn stderr_tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+
+    match stderr.char_indices().rev().nth(STDERR_TAIL_LEN) {
+        Some((index, _)) => format!("...{}", &stderr[index..]),
+        None => stderr.to_string(),
+    }
+}
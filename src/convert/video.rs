@@ -0,0 +1,267 @@
+use std::path::Path;
+
+use anyhow::Context;
+use image::RgbaImage;
+use tokio::fs;
+
+use crate::convert::transcoder::Transcoder;
+use crate::convert::{still, AnimationOptions, OutputFormat};
+
+/// Convert a webm video to the given output format.
+///
+/// Still formats take the first frame of the video.
+#[tracing::instrument(skip(transcoder, video))]
+pub async fn convert_webm(
+    transcoder: &Transcoder,
+    video: &[u8],
+    format: OutputFormat,
+    options: AnimationOptions,
+) -> anyhow::Result<Vec<u8>> {
+    #[cfg(feature = "native-video")]
+    if crate::convert::native::supports(format) {
+        match crate::convert::native::convert_webm(video, format, options).await {
+            Ok(data) => return Ok(data),
+            Err(e) => log::warn!(
+                "In-process video conversion failed, falling back to ffmpeg: {:#}",
+                e
+            ),
+        }
+    }
+
+    let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    log::debug!("Temporary directory: {:?}", temp_dir.path());
+
+    let video_path = temp_dir.path().join("video.webm");
+    fs::write(&video_path, video)
+        .await
+        .context("Failed to write video to disk")?;
+
+    // ffmpeg's native vp9 decoder drops the alpha channel of video stickers
+    let input = ["-c:v", "libvpx-vp9", "-i", video_path.to_str().unwrap()].map(String::from);
+    let scale = match format {
+        // yuv420p needs even dimensions
+        OutputFormat::Mp4 => format!("scale={}:-2:flags=lanczos", options.width),
+        _ => format!("scale={}:-1:flags=lanczos", options.width),
+    };
+
+    match format {
+        OutputFormat::Png | OutputFormat::Webp => {
+            let frame = run_ffmpeg(
+                transcoder,
+                temp_dir.path(),
+                &input,
+                &scale,
+                OutputFormat::Png,
+            )
+            .await?;
+            if format == OutputFormat::Webp {
+                let frame = image::load_from_memory(&frame)
+                    .context("Failed to decode video frame")?
+                    .into_rgba8();
+                still::encode_webp(&frame)
+            } else {
+                Ok(frame)
+            }
+        }
+        _ => {
+            let filter = format!("fps={},{}", options.fps, scale);
+            run_ffmpeg(transcoder, temp_dir.path(), &input, &filter, format).await
+        }
+    }
+}
+
+/// Encode rendered RGBA frames to an animated format with ffmpeg.
+#[tracing::instrument(skip(transcoder, frames))]
+pub async fn encode_frames(
+    transcoder: &Transcoder,
+    frames: &[RgbaImage],
+    format: OutputFormat,
+    fps: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let first = frames.first().context("No frames to encode")?;
+    let (width, height) = first.dimensions();
+
+    let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    log::debug!("Temporary directory: {:?}", temp_dir.path());
+
+    let frames_path = temp_dir.path().join("frames.rgba");
+    let raw = frames
+        .iter()
+        .flat_map(|frame| frame.as_raw().iter().copied())
+        .collect::<Vec<_>>();
+    fs::write(&frames_path, raw)
+        .await
+        .context("Failed to write frames to disk")?;
+
+    let input = [
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        "rgba",
+        "-s",
+        &format!("{}x{}", width, height),
+        "-r",
+        &fps.to_string(),
+        "-i",
+        frames_path.to_str().unwrap(),
+    ]
+    .map(String::from);
+
+    let filter = match format {
+        // yuv420p needs even dimensions
+        OutputFormat::Mp4 => "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+        _ => "null",
+    };
+
+    run_ffmpeg(transcoder, temp_dir.path(), &input, filter, format).await
+}
+
+/// Run ffmpeg with the given input arguments and video filter, returning the encoded output.
+async fn run_ffmpeg(
+    transcoder: &Transcoder,
+    temp_dir: &Path,
+    input: &[String],
+    filter: &str,
+    format: OutputFormat,
+) -> anyhow::Result<Vec<u8>> {
+    let (file_name, output_args): (&str, &[&str]) = match format {
+        OutputFormat::Png => (
+            "frame.png",
+            &["-frames:v", "1", "-pix_fmt", "rgba", "-f", "image2"],
+        ),
+        OutputFormat::Gif => ("video.gif", &["-c:v", "gif", "-f", "gif"]),
+        OutputFormat::Apng => (
+            "video.png",
+            &["-pix_fmt", "rgba", "-plays", "0", "-f", "apng"],
+        ),
+        OutputFormat::AnimatedWebp => (
+            "video.webp",
+            &[
+                "-c:v",
+                "libwebp_anim",
+                "-pix_fmt",
+                "bgra",
+                "-lossless",
+                "1",
+                "-loop",
+                "0",
+                "-f",
+                "webp",
+            ],
+        ),
+        OutputFormat::Mp4 => (
+            "video.mp4",
+            &[
+                "-c:v",
+                "libx264",
+                "-pix_fmt",
+                "yuv420p",
+                "-movflags",
+                "+faststart",
+                "-f",
+                "mp4",
+            ],
+        ),
+        _ => return Err(anyhow::anyhow!("ffmpeg can't produce {}", format)),
+    };
+    let output_path = temp_dir.join(file_name);
+
+    let filter = match format {
+        // a generated palette with a reserved transparent entry keeps both colours and alpha
+        OutputFormat::Gif => format!(
+            "{},split[s0][s1];[s0]palettegen=reserve_transparent=1[p];[s1][p]paletteuse=alpha_threshold=128",
+            filter
+        ),
+        _ => filter.to_string(),
+    };
+
+    transcoder
+        .run(
+            input
+                .iter()
+                .map(String::as_str)
+                .chain(["-vf", &filter])
+                .chain(output_args.iter().copied())
+                .chain([output_path.to_str().unwrap()]),
+        )
+        .await
+        .with_context(|| format!("Failed to convert video to {}", format))?;
+
+    let data = fs::read(&output_path)
+        .await
+        .with_context(|| format!("Failed to read {} from disk", format))?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use image::ImageFormat;
+
+    use super::*;
+
+    /// An 8x8 VP9 video of two grey frames, with an alpha stream of 86 everywhere.
+    const ALPHA_WEBM: &[u8] = include_bytes!("../../tests/fixtures/alpha.webm");
+
+    /// Convert the fixture with the ffmpeg on `PATH`, returning the first frame.
+    async fn convert_fixture(format: OutputFormat) -> RgbaImage {
+        let transcoder = Transcoder::new("ffmpeg", Duration::from_secs(30));
+        let options = AnimationOptions { fps: 30, width: 64 };
+        let data = convert_webm(&transcoder, ALPHA_WEBM, format, options)
+            .await
+            .unwrap();
+        let image_format = match format {
+            OutputFormat::Gif => ImageFormat::Gif,
+            OutputFormat::AnimatedWebp => ImageFormat::WebP,
+            _ => ImageFormat::Png,
+        };
+
+        let frame = image::load_from_memory_with_format(&data, image_format)
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(frame.width(), 64);
+        frame
+    }
+
+    fn corners(image: &RgbaImage) -> [u8; 4] {
+        let (width, height) = image.dimensions();
+        [
+            (0, 0),
+            (width - 1, 0),
+            (0, height - 1),
+            (width - 1, height - 1),
+        ]
+        .map(|(x, y)| image.get_pixel(x, y)[3])
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg built with libvpx"]
+    async fn gif_keeps_transparency() {
+        let frame = convert_fixture(OutputFormat::Gif).await;
+        // pixels drawn with the transparent palette index decode as fully transparent
+        assert_eq!(corners(&frame), [0; 4]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg built with libvpx"]
+    async fn png_keeps_alpha() {
+        let frame = convert_fixture(OutputFormat::Png).await;
+        assert!(corners(&frame).iter().all(|&alpha| alpha < 255));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg built with libvpx"]
+    async fn apng_keeps_alpha() {
+        let frame = convert_fixture(OutputFormat::Apng).await;
+        assert!(corners(&frame).iter().all(|&alpha| alpha < 255));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs ffmpeg built with libvpx"]
+    async fn animated_webp_keeps_alpha() {
+        let frame = convert_fixture(OutputFormat::AnimatedWebp).await;
+        assert!(corners(&frame).iter().all(|&alpha| alpha < 255));
+    }
+}
@@ -0,0 +1,142 @@
+//! Safe wrapper around the libvpx VP9 decoder.
+
+use std::ptr;
+
+use anyhow::Context;
+use image::RgbaImage;
+use vpx_sys::*;
+
+/// An in-process VP9 decoder.
+pub struct Vp9Decoder {
+    // libvpx keeps pointers into the context, so it must not move after init
+    ctx: Box<vpx_codec_ctx_t>,
+}
+
+// the context is only ever used through `&mut self`
+unsafe impl Send for Vp9Decoder {}
+
+impl Vp9Decoder {
+    pub fn new() -> anyhow::Result<Self> {
+        let mut ctx: Box<vpx_codec_ctx_t> = Box::new(unsafe { std::mem::zeroed() });
+
+        let err = unsafe {
+            vpx_codec_dec_init_ver(
+                &mut *ctx,
+                vpx_codec_vp9_dx(),
+                ptr::null(),
+                0,
+                VPX_DECODER_ABI_VERSION as i32,
+            )
+        };
+        check(err).context("Failed to initialize VP9 decoder")?;
+
+        Ok(Self { ctx })
+    }
+
+    /// Decode a frame to RGBA, with an opaque alpha channel.
+    pub fn decode_rgba(&mut self, data: &[u8]) -> anyhow::Result<Option<RgbaImage>> {
+        self.decode(data, |img| {
+            if img.fmt != vpx_img_fmt::VPX_IMG_FMT_I420 {
+                return Err(anyhow::anyhow!(
+                    "Unsupported VP9 pixel format {:?}",
+                    img.fmt
+                ));
+            }
+
+            let (width, height) = (img.d_w as usize, img.d_h as usize);
+            let mut pixels = Vec::with_capacity(width * height * 4);
+
+            for row in 0..height {
+                for col in 0..width {
+                    let (y, u, v) = unsafe {
+                        (
+                            *plane_pixel(img, 0, row, col),
+                            *plane_pixel(img, 1, row >> 1, col >> 1),
+                            *plane_pixel(img, 2, row >> 1, col >> 1),
+                        )
+                    };
+                    let [r, g, b] = yuv_to_rgb(y, u, v);
+                    pixels.extend_from_slice(&[r, g, b, u8::MAX]);
+                }
+            }
+
+            RgbaImage::from_raw(width as u32, height as u32, pixels)
+                .context("Decoded frame has an unexpected size")
+        })
+    }
+
+    /// Decode a frame and return only its luma plane, which carries the alpha stream.
+    pub fn decode_luma(&mut self, data: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        self.decode(data, |img| {
+            let (width, height) = (img.d_w as usize, img.d_h as usize);
+            let mut luma = Vec::with_capacity(width * height);
+
+            for row in 0..height {
+                for col in 0..width {
+                    luma.push(unsafe { *plane_pixel(img, 0, row, col) });
+                }
+            }
+
+            Ok(luma)
+        })
+    }
+
+    fn decode<T>(
+        &mut self,
+        data: &[u8],
+        f: impl FnOnce(&vpx_image_t) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        let err = unsafe {
+            vpx_codec_decode(
+                &mut *self.ctx,
+                data.as_ptr(),
+                data.len() as _,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        check(err).context("Failed to decode VP9 frame")?;
+
+        // VP9 emits at most one frame per packet, superframes are handled by libvpx
+        let mut iter: vpx_codec_iter_t = ptr::null();
+        let img = unsafe { vpx_codec_get_frame(&mut *self.ctx, &mut iter) };
+        if img.is_null() {
+            return Ok(None);
+        }
+
+        f(unsafe { &*img }).map(Some)
+    }
+}
+
+impl Drop for Vp9Decoder {
+    fn drop(&mut self) {
+        unsafe {
+            vpx_codec_destroy(&mut *self.ctx);
+        }
+    }
+}
+
+fn check(err: vpx_codec_err_t) -> anyhow::Result<()> {
+    match err {
+        vpx_codec_err_t::VPX_CODEC_OK => Ok(()),
+        err => Err(anyhow::anyhow!("libvpx error {:?}", err)),
+    }
+}
+
+/// Pointer to a sample of a plane, `row` and `col` are in plane coordinates.
+unsafe fn plane_pixel(img: &vpx_image_t, plane: usize, row: usize, col: usize) -> *const u8 {
+    img.planes[plane].offset(row as isize * img.stride[plane] as isize + col as isize)
+}
+
+/// Convert a BT.601 limited range sample to RGB.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as i32 - 16) * 298;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+
+    [
+        ((c + 409 * e + 128) >> 8).clamp(0, 255) as u8,
+        ((c - 100 * d - 208 * e + 128) >> 8).clamp(0, 255) as u8,
+        ((c + 516 * d + 128) >> 8).clamp(0, 255) as u8,
+    ]
+}
@@ -0,0 +1,442 @@
+//! A minimal WebM demuxer, just enough to pull the VP9 frames (and their alpha
+//! streams) out of Telegram video stickers.
+
+use anyhow::Context;
+
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const CLUSTER: u32 = 0x1F43_B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const BLOCK_ADDITIONS: u32 = 0x75A1;
+const BLOCK_MORE: u32 = 0xA6;
+const BLOCK_ADD_ID: u32 = 0xEE;
+const BLOCK_ADDITIONAL: u32 = 0xA5;
+
+/// Master elements the demuxer descends into, everything else is read or skipped whole.
+const MASTER_ELEMENTS: [u32; 8] = [
+    SEGMENT,
+    INFO,
+    TRACKS,
+    TRACK_ENTRY,
+    CLUSTER,
+    BLOCK_GROUP,
+    BLOCK_ADDITIONS,
+    BLOCK_MORE,
+];
+
+/// The video track of a WebM file.
+#[derive(Debug)]
+pub struct WebmVideo {
+    pub codec_id: String,
+    pub frames: Vec<WebmFrame>,
+}
+
+/// A single compressed video frame.
+#[derive(Debug)]
+pub struct WebmFrame {
+    /// Presentation time in seconds.
+    pub timestamp: f64,
+    pub data: Vec<u8>,
+    /// The alpha stream of the frame (`BlockAdditional` with id 1), if any.
+    pub alpha: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    number: u64,
+    kind: u64,
+    codec_id: String,
+}
+
+#[derive(Debug)]
+struct Block {
+    track: u64,
+    timestamp: i64,
+    data: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+/// Demux the first video track of a WebM file, frames are sorted by timestamp.
+#[tracing::instrument(skip(data))]
+pub fn demux(data: &[u8]) -> anyhow::Result<WebmVideo> {
+    let mut timestamp_scale = 1_000_000u64;
+    let mut tracks = Vec::new();
+    let mut blocks = Vec::new();
+
+    let mut track = Track::default();
+    let mut cluster_timestamp = 0u64;
+    let mut group_block: Option<Block> = None;
+    let mut group_alpha = None;
+    let mut more_id = 1u64;
+    let mut more_data = None;
+
+    // open master elements with the offset they end at
+    let mut stack: Vec<(u32, usize)> = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        // close the master elements that end here
+        while let Some(&(id, end)) = stack.last() {
+            if pos < end {
+                break;
+            }
+            stack.pop();
+
+            match id {
+                TRACK_ENTRY => tracks.push(std::mem::take(&mut track)),
+                BLOCK_MORE if more_id == 1 => group_alpha = more_data.take(),
+                BLOCK_GROUP => {
+                    if let Some(mut block) = group_block.take() {
+                        block.alpha = group_alpha.take();
+                        blocks.push(block);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if pos >= data.len() {
+            break;
+        }
+
+        let (id, id_len) = read_vint(&data[pos..], true).context("Invalid element id")?;
+        let (size, size_len) =
+            read_vint(&data[pos + id_len..], false).context("Invalid element size")?;
+        let id = id as u32;
+        let start = pos + id_len + size_len;
+
+        let parent_end = stack.last().map(|&(_, end)| end).unwrap_or(data.len());
+        // an all-ones size means the element extends to the end of its parent
+        let end = if size == (1 << (7 * size_len)) - 1 {
+            parent_end
+        } else {
+            start.saturating_add(size as usize).min(parent_end)
+        };
+        let body = &data[start.min(end)..end];
+
+        if MASTER_ELEMENTS.contains(&id) {
+            // unknown-sized clusters are only terminated by the next cluster
+            if let Some(index) = stack.iter().position(|&(open, _)| open == id) {
+                stack.truncate(index);
+            }
+
+            match id {
+                TRACK_ENTRY => track = Track::default(),
+                BLOCK_GROUP => {
+                    group_block = None;
+                    group_alpha = None;
+                }
+                BLOCK_MORE => {
+                    more_id = 1;
+                    more_data = None;
+                }
+                _ => {}
+            }
+
+            stack.push((id, end));
+            pos = start;
+            continue;
+        }
+
+        match id {
+            TIMESTAMP_SCALE => timestamp_scale = read_uint(body),
+            TRACK_NUMBER => track.number = read_uint(body),
+            TRACK_TYPE => track.kind = read_uint(body),
+            CODEC_ID => {
+                track.codec_id = String::from_utf8_lossy(body).trim_end_matches('\0').into()
+            }
+            CLUSTER_TIMESTAMP => cluster_timestamp = read_uint(body),
+            SIMPLE_BLOCK => blocks.push(read_block(body, cluster_timestamp)?),
+            BLOCK => group_block = Some(read_block(body, cluster_timestamp)?),
+            BLOCK_ADD_ID => more_id = read_uint(body),
+            BLOCK_ADDITIONAL => more_data = Some(body.to_vec()),
+            _ => {}
+        }
+
+        pos = end;
+    }
+
+    // video tracks have type 1
+    let video = tracks
+        .into_iter()
+        .find(|track| track.kind == 1)
+        .context("No video track found")?;
+
+    let mut frames = blocks
+        .into_iter()
+        .filter(|block| block.track == video.number)
+        .map(|block| WebmFrame {
+            timestamp: block.timestamp.max(0) as f64 * timestamp_scale as f64 / 1e9,
+            data: block.data,
+            alpha: block.alpha,
+        })
+        .collect::<Vec<_>>();
+    frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    Ok(WebmVideo {
+        codec_id: video.codec_id,
+        frames,
+    })
+}
+
+/// Read an EBML variable length integer, returning it with its length in bytes.
+///
+/// Element ids keep their length marker, sizes and track numbers don't.
+fn read_vint(data: &[u8], keep_marker: bool) -> anyhow::Result<(u64, usize)> {
+    let first = *data.first().context("Unexpected end of file")?;
+    if first == 0 {
+        return Err(anyhow::anyhow!("Variable length integer is too long"));
+    }
+
+    let len = first.leading_zeros() as usize + 1;
+    let rest = data.get(1..len).context("Unexpected end of file")?;
+
+    let first = if keep_marker {
+        first
+    } else {
+        first & (0xFFu16 >> len) as u8
+    };
+
+    Ok((
+        rest.iter()
+            .fold(first as u64, |value, &b| value << 8 | b as u64),
+        len,
+    ))
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, &b| value << 8 | b as u64)
+}
+
+/// Parse the header of a (Simple)Block, laced blocks aren't used by video stickers.
+fn read_block(data: &[u8], cluster_timestamp: u64) -> anyhow::Result<Block> {
+    let (track, len) = read_vint(data, false).context("Invalid block track number")?;
+    let header = data.get(len..len + 3).context("Truncated block header")?;
+
+    let relative = i16::from_be_bytes([header[0], header[1]]);
+    if header[2] & 0x06 != 0 {
+        return Err(anyhow::anyhow!("Laced blocks are not supported"));
+    }
+
+    Ok(Block {
+        track,
+        timestamp: cluster_timestamp as i64 + relative as i64,
+        data: data[len + 3..].to_vec(),
+        alpha: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An EBML element with a one byte size.
+    fn element(id: u32, body: &[u8]) -> Vec<u8> {
+        assert!(body.len() < 0x7F);
+        let mut element = id_bytes(id);
+        element.push(0x80 | body.len() as u8);
+        element.extend_from_slice(body);
+        element
+    }
+
+    /// An EBML element of unknown size, extending to the end of its parent.
+    fn unknown_size_element(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut element = id_bytes(id);
+        element.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        element.extend_from_slice(body);
+        element
+    }
+
+    fn id_bytes(id: u32) -> Vec<u8> {
+        id.to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect()
+    }
+
+    fn block_body(track: u8, relative: i16, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x80 | track];
+        body.extend_from_slice(&relative.to_be_bytes());
+        body.push(0x80);
+        body.extend_from_slice(data);
+        body
+    }
+
+    fn tracks() -> Vec<u8> {
+        let audio = [
+            element(TRACK_NUMBER, &[1]),
+            element(TRACK_TYPE, &[2]),
+            element(CODEC_ID, b"A_OPUS"),
+        ]
+        .concat();
+        let video = [
+            element(TRACK_NUMBER, &[2]),
+            element(TRACK_TYPE, &[1]),
+            element(CODEC_ID, b"V_VP9"),
+        ]
+        .concat();
+
+        element(
+            TRACKS,
+            &[element(TRACK_ENTRY, &audio), element(TRACK_ENTRY, &video)].concat(),
+        )
+    }
+
+    #[test]
+    fn reads_vints() {
+        assert_eq!(read_vint(&[0x81], false).unwrap(), (1, 1));
+        assert_eq!(read_vint(&[0x40, 0x02], false).unwrap(), (2, 2));
+        assert_eq!(
+            read_vint(&[0x1A, 0x45, 0xDF, 0xA3], true).unwrap(),
+            (0x1A45_DFA3, 4)
+        );
+        assert_eq!(
+            read_vint(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], false).unwrap(),
+            ((1 << 56) - 1, 8)
+        );
+        assert!(read_vint(&[], false).is_err());
+        assert!(read_vint(&[0x00, 0x81], false).is_err());
+        // the marker announces more bytes than there are
+        assert!(read_vint(&[0x20, 0x01], false).is_err());
+    }
+
+    #[test]
+    fn demuxes_blocks_and_alpha() {
+        let info = element(INFO, &element(TIMESTAMP_SCALE, &[0x0F, 0x42, 0x40]));
+        let alpha = element(
+            BLOCK_ADDITIONS,
+            &element(
+                BLOCK_MORE,
+                &[
+                    element(BLOCK_ADD_ID, &[1]),
+                    element(BLOCK_ADDITIONAL, b"alpha"),
+                ]
+                .concat(),
+            ),
+        );
+        let cluster = element(
+            CLUSTER,
+            &[
+                element(CLUSTER_TIMESTAMP, &[100]),
+                // out of order, frames are sorted by timestamp
+                element(
+                    BLOCK_GROUP,
+                    &[element(BLOCK, &block_body(2, 40, b"second")), alpha].concat(),
+                ),
+                element(SIMPLE_BLOCK, &block_body(1, 0, b"audio")),
+                element(SIMPLE_BLOCK, &block_body(2, 0, b"first")),
+            ]
+            .concat(),
+        );
+        let file = element(SEGMENT, &[info, tracks(), cluster].concat());
+
+        let video = demux(&file).unwrap();
+        assert_eq!(video.codec_id, "V_VP9");
+        assert_eq!(video.frames.len(), 2);
+        assert_eq!(video.frames[0].data, b"first");
+        assert_eq!(video.frames[0].timestamp, 0.1);
+        assert_eq!(video.frames[0].alpha, None);
+        assert_eq!(video.frames[1].data, b"second");
+        assert_eq!(video.frames[1].timestamp, 0.14);
+        assert_eq!(video.frames[1].alpha.as_deref(), Some(&b"alpha"[..]));
+    }
+
+    #[test]
+    fn demuxes_unknown_size_elements() {
+        // live encoders write the segment and clusters with an unknown size,
+        // a cluster then ends at the next one
+        let clusters = [
+            unknown_size_element(
+                CLUSTER,
+                &[
+                    element(CLUSTER_TIMESTAMP, &[0]),
+                    element(SIMPLE_BLOCK, &block_body(2, 0, b"first")),
+                ]
+                .concat(),
+            ),
+            unknown_size_element(
+                CLUSTER,
+                &[
+                    element(CLUSTER_TIMESTAMP, &[33]),
+                    element(SIMPLE_BLOCK, &block_body(2, 0, b"second")),
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+        let file = unknown_size_element(SEGMENT, &[tracks(), clusters].concat());
+
+        let video = demux(&file).unwrap();
+        let frames = video
+            .frames
+            .iter()
+            .map(|frame| (frame.timestamp, frame.data.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [(0.0, &b"first"[..]), (0.033, &b"second"[..])]);
+    }
+
+    #[test]
+    fn clamps_truncated_elements() {
+        let cluster = element(
+            CLUSTER,
+            &[
+                element(CLUSTER_TIMESTAMP, &[0]),
+                element(SIMPLE_BLOCK, &block_body(2, 0, b"first")),
+                element(SIMPLE_BLOCK, &block_body(2, 33, b"second")),
+            ]
+            .concat(),
+        );
+        let mut file = element(SEGMENT, &[tracks(), cluster].concat());
+        // cut the file in the middle of the last frame
+        file.truncate(file.len() - 3);
+
+        let video = demux(&file).unwrap();
+        assert_eq!(video.frames.len(), 2);
+        assert_eq!(video.frames[1].data, b"sec");
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let cluster = element(
+            CLUSTER,
+            &[
+                element(CLUSTER_TIMESTAMP, &[0]),
+                element(SIMPLE_BLOCK, &block_body(2, 0, b"first")),
+            ]
+            .concat(),
+        );
+        let file = element(SEGMENT, &[tracks(), cluster].concat());
+
+        // cut inside the block header, then inside the size of the block element
+        let header_end = file.len() - b"first".len();
+        assert!(demux(&file[..header_end - 2]).is_err());
+        let block_start = header_end - 4 - 2;
+        let mut file = file[..block_start + 1].to_vec();
+        file.push(0x40);
+        assert!(demux(&file).is_err());
+    }
+
+    #[test]
+    fn requires_a_video_track() {
+        assert!(demux(&element(SEGMENT, &[])).is_err());
+    }
+
+    #[test]
+    fn demuxes_alpha_fixture() {
+        let video = demux(include_bytes!("../../tests/fixtures/alpha.webm")).unwrap();
+
+        assert_eq!(video.codec_id, "V_VP9");
+        assert_eq!(video.frames.len(), 2);
+        assert_eq!(video.frames[1].timestamp, 0.033);
+        assert!(video.frames.iter().all(|frame| frame.alpha.is_some()));
+    }
+}
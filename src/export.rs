@@ -0,0 +1,390 @@
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use teloxide::prelude::*;
+use teloxide::types::{Sticker, StickerSet};
+
+use crate::archive::{ArchiveFormat, ArchiveParts, ArchiveTask};
+use crate::convert::transcoder::Transcoder;
+use crate::convert::{AnimationOptions, OutputFormat};
+use crate::i18n::Locale;
+use crate::jobs::Jobs;
+use crate::manifest::Manifest;
+use crate::naming::{ArchiveNames, FilenameTemplate};
+use crate::observability::metrics::metrics;
+use crate::pool::ConversionPool;
+use crate::util::{edit_status, export_single_sticker};
+
+/// Exports stickers and sticker sets, shared by every way an export can be requested.
+#[derive(Debug)]
+pub struct Exporter {
+    transcoder: Arc<Transcoder>,
+    pool: Arc<ConversionPool>,
+    /// Maximum size of an archive part, in bytes.
+    split_size: u64,
+    /// Pack exports running in the background.
+    pub jobs: Arc<Jobs>,
+}
+
+/// How stickers are exported, resolved from the request, the user's settings
+/// and the bot's defaults.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub format: OutputFormat,
+    pub archive: ArchiveFormat,
+    pub filename_template: FilenameTemplate,
+    pub animation: AnimationOptions,
+    /// Whether pack archives include `manifest.json`.
+    pub manifest: bool,
+}
+
+/// A finished pack export, its archive parts are removed when dropped.
+pub struct PackExport {
+    pub set_name: String,
+    pub format: ArchiveFormat,
+    pub parts: ArchiveParts,
+    pub exported: usize,
+    pub total: usize,
+}
+
+impl Exporter {
+    pub fn new(
+        transcoder: Arc<Transcoder>,
+        pool: Arc<ConversionPool>,
+        split_size: u64,
+        jobs: Arc<Jobs>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            transcoder,
+            pool,
+            split_size,
+            jobs,
+        })
+    }
+
+    /// Export a single sticker on behalf of `user`, returning its file name and data.
+    #[tracing::instrument(skip(self, bot))]
+    pub async fn export_sticker(
+        &self,
+        bot: &Bot,
+        user: i64,
+        sticker: &Sticker,
+        options: &ExportOptions,
+    ) -> anyhow::Result<(String, Vec<u8>)> {
+        let result = self
+            .pool
+            .run(
+                user,
+                export_single_sticker(
+                    bot.clone(),
+                    &self.transcoder,
+                    sticker,
+                    options.format,
+                    options.animation,
+                ),
+            )
+            .await;
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        metrics().record_export("sticker", options.format, outcome);
+        let (extension, data) = result?;
+
+        Ok((format!("{}.{}", sticker.file.unique_id, extension), data))
+    }
+
+    /// Export a sticker set on behalf of `user`, reporting progress by editing `status`.
+    ///
+    /// Stickers that fail to export are retried once, then left out of the archive
+    /// and listed in `errors.txt` and the manifest.
+    #[tracing::instrument(skip(self, bot, sticker_set, status), fields(set_name = %sticker_set.name))]
+    pub async fn export_pack(
+        &self,
+        bot: &Bot,
+        user: i64,
+        sticker_set: &StickerSet,
+        options: &ExportOptions,
+        status: &Message,
+        locale: &Locale,
+    ) -> anyhow::Result<PackExport> {
+        let result = self
+            .build_pack(bot, user, sticker_set, options, status, locale)
+            .await;
+
+        let outcome = match &result {
+            Ok(export) if export.exported == export.total => "success",
+            Ok(_) => "partial",
+            Err(_) => "failure",
+        };
+        metrics().record_export("pack", options.format, outcome);
+
+        result
+    }
+
+    async fn build_pack(
+        &self,
+        bot: &Bot,
+        user: i64,
+        sticker_set: &StickerSet,
+        options: &ExportOptions,
+        status: &Message,
+        locale: &Locale,
+    ) -> anyhow::Result<PackExport> {
+        let format = options.format;
+        let mut futures = FuturesUnordered::new();
+        let stickers_len = sticker_set.stickers.len();
+
+        // Name the files after their position in the pack, regardless of completion order
+        let mut names = ArchiveNames::default();
+        let manifest_filename = names.reserve("manifest", "json");
+        let errors_filename = names.reserve("errors", "txt");
+        let filenames = sticker_set
+            .stickers
+            .iter()
+            .enumerate()
+            .map(|(index, sticker)| {
+                let stem = options
+                    .filename_template
+                    .render(index, stickers_len, sticker);
+                let extension = format.resolve(&sticker.format).extension(&sticker.format);
+                let filename = names.reserve(&stem, extension);
+                (stem, filename)
+            })
+            .collect::<Vec<_>>();
+
+        for (index, sticker) in sticker_set.stickers.iter().enumerate() {
+            futures.push(async move {
+                let export = || {
+                    self.pool.run(
+                        user,
+                        export_single_sticker(
+                            bot.clone(),
+                            &self.transcoder,
+                            sticker,
+                            format,
+                            options.animation,
+                        ),
+                    )
+                };
+
+                (index, sticker, retry_once(index, export).await)
+            });
+        }
+
+        // Stream the stickers into the archive as they are converted
+        let mut archive = ArchiveTask::spawn(options.archive, self.split_size)?;
+        let mut manifest = Manifest::new(sticker_set, format);
+        let mut failures = Vec::new();
+        let mut downloaded_len = 0;
+
+        while let Some((index, sticker, result)) = futures.next().await {
+            match result {
+                Ok((extension, data)) => {
+                    let (stem, filename) = &filenames[index];
+                    // the file turned out to be of another format than its metadata said
+                    let filename = if filename.ends_with(&format!(".{}", extension)) {
+                        filename.clone()
+                    } else {
+                        names.reserve(stem, extension)
+                    };
+
+                    archive.add_file(&filename, data).await?;
+                    manifest.add_sticker(index, sticker, &filename);
+                }
+                Err(e) => {
+                    log::error!("Failed to export sticker {}: {:#}", index, e);
+                    failures.push((index, sticker, e));
+                }
+            }
+            downloaded_len += 1;
+
+            // Update progress every 5 stickers
+            if downloaded_len % 5 == 0 || downloaded_len == stickers_len {
+                let progress = locale.text_with(
+                    "downloading",
+                    [
+                        ("done", downloaded_len.into()),
+                        ("total", stickers_len.into()),
+                    ],
+                );
+                // a failed progress update is no reason to give up on the export
+                if let Err(e) = edit_status(bot, status, progress).await {
+                    log::warn!("Failed to update the export progress: {:#}", e);
+                }
+            }
+        }
+
+        let exported_len = stickers_len - failures.len();
+        if exported_len == 0 {
+            return Err(match failures.into_iter().next() {
+                Some((_, _, e)) => e.context("Failed to export any sticker from the pack"),
+                None => anyhow::anyhow!("The sticker pack is empty"),
+            });
+        }
+
+        // List the stickers that couldn't be exported, in pack order
+        failures.sort_by_key(|(index, ..)| *index);
+        if !failures.is_empty() {
+            let mut errors = String::new();
+            for (index, sticker, e) in &failures {
+                metrics().record_error(e);
+                let error = format!("{:#}", e);
+                manifest.add_failure(*index, sticker, &error);
+                errors.push_str(&error_line(*index, sticker, &error));
+            }
+            archive
+                .add_file(&errors_filename, errors.into_bytes())
+                .await?;
+        }
+
+        if options.manifest {
+            archive
+                .add_file(&manifest_filename, manifest.to_json()?)
+                .await?;
+        }
+
+        Ok(PackExport {
+            set_name: sticker_set.name.clone(),
+            format: options.archive,
+            parts: archive.finish().await?,
+            exported: exported_len,
+            total: stickers_len,
+        })
+    }
+}
+
+/// Export the sticker at `index` in its pack, retrying once.
+///
+/// Most failures are flaky downloads or conversion timeouts.
+async fn retry_once<T, F, Fut>(index: usize, export: F) -> anyhow::Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    match export().await {
+        Ok(file) => Ok(file),
+        Err(e) => {
+            log::warn!("Failed to export sticker {}, retrying: {:#}", index, e);
+            export().await
+        }
+    }
+}
+
+/// The line of `errors.txt` listing a sticker that couldn't be exported.
+fn error_line(index: usize, sticker: &Sticker, error: &str) -> String {
+    format!(
+        "#{} {} {}: {}\n",
+        index + 1,
+        sticker.file.unique_id,
+        sticker.emoji.as_deref().unwrap_or_default(),
+        error
+    )
+}
+
+impl PackExport {
+    /// The archive parts with the file names they are sent as.
+    pub fn files(&self) -> impl Iterator<Item = (String, &Path)> {
+        let parts_len = self.parts.paths.len();
+
+        self.parts.paths.iter().enumerate().map(move |(i, path)| {
+            let filename = if parts_len == 1 {
+                format!("stickers-{}.{}", self.set_name, self.format.extension())
+            } else {
+                format!(
+                    "stickers-{}.part{}.{}",
+                    self.set_name,
+                    i + 1,
+                    self.format.extension()
+                )
+            };
+
+            (filename, path.as_path())
+        })
+    }
+
+    /// A short summary of the export, sent along with the last part.
+    pub fn summary(&self, locale: &Locale) -> String {
+        let id = if self.exported == self.total {
+            "summary"
+        } else {
+            "summary-failures"
+        };
+
+        locale.text_with(
+            id,
+            [
+                ("exported", self.exported.into()),
+                ("total", self.total.into()),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Export that fails the first `failures` times it is attempted.
+    fn flaky(attempts: &AtomicUsize, failures: usize) -> anyhow::Result<&'static str> {
+        if attempts.fetch_add(1, Ordering::Relaxed) < failures {
+            Err(anyhow::anyhow!("Failed to download sticker"))
+        } else {
+            Ok("gif")
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_exported_stickers() {
+        let attempts = AtomicUsize::new(0);
+        let result = retry_once(0, || async { flaky(&attempts, 0) }).await;
+
+        assert_eq!(result.unwrap(), "gif");
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_a_failed_sticker_once() {
+        let attempts = AtomicUsize::new(0);
+        let result = retry_once(0, || async { flaky(&attempts, 1) }).await;
+
+        assert_eq!(result.unwrap(), "gif");
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retry() {
+        let attempts = AtomicUsize::new(0);
+        let result = retry_once(0, || async { flaky(&attempts, 2) }).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Failed to download sticker"
+        );
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn lists_failures_by_position() {
+        let sticker: Sticker = serde_json::from_value(json!({
+            "file_id": "CAACAgIAAxAgADaaa",
+            "file_unique_id": "AgADaaa",
+            "width": 512,
+            "height": 512,
+            "is_animated": false,
+            "is_video": true,
+            "type": "regular",
+            "emoji": "😺",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            error_line(2, &sticker, "Failed to convert video: ffmpeg timed out"),
+            "#3 AgADaaa 😺: Failed to convert video: ffmpeg timed out\n"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use teloxide::dispatching::dialogue::ErasedStorage;
+
+use crate::limiter::Limiter;
+use crate::storage::{Namespace, Versioned};
+
+/// Settings of a group chat, changed by its administrators.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupSettings {
+    pub exports_enabled: bool,
+}
+
+impl Default for GroupSettings {
+    fn default() -> Self {
+        Self {
+            exports_enabled: true,
+        }
+    }
+}
+
+impl Versioned for GroupSettings {
+    const VERSION: u64 = 0;
+
+    const MIGRATIONS: &'static [fn(Value) -> Value] = &[];
+}
+
+/// State of the group chats the bot is used in.
+///
+/// Exports are enabled in every group until an administrator disables them,
+/// and each group shares one rate limit between all of its members.
+pub struct Groups {
+    storage: Arc<ErasedStorage<GroupSettings>>,
+    pub rate_limiter: Arc<Limiter<i64>>,
+}
+
+impl Debug for Groups {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Groups")
+            .field("rate_limiter", &self.rate_limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Groups {
+    pub fn new(
+        storage: Arc<ErasedStorage<GroupSettings>>,
+        rate_limiter: Arc<Limiter<i64>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            storage,
+            rate_limiter,
+        })
+    }
+
+    /// The settings of a group, the defaults if they were never changed.
+    async fn get(&self, chat: i64) -> anyhow::Result<GroupSettings> {
+        Ok(self
+            .storage
+            .clone()
+            .get_dialogue(Namespace::GroupSettings.key(chat))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get group settings: {}", e))?
+            .unwrap_or_default())
+    }
+
+    pub async fn is_enabled(&self, chat: i64) -> anyhow::Result<bool> {
+        Ok(self.get(chat).await?.exports_enabled)
+    }
+
+    pub async fn set_enabled(&self, chat: i64, enabled: bool) -> anyhow::Result<()> {
+        let settings = GroupSettings {
+            exports_enabled: enabled,
+        };
+
+        self.storage
+            .clone()
+            .update_dialogue(Namespace::GroupSettings.key(chat), settings)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update group settings: {}", e))
+    }
+}
@@ -1,59 +1,299 @@
-use std::io::Write;
 use std::sync::Arc;
 
 use anyhow::Context;
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
-use teloxide::dispatching::dialogue::InMemStorage;
+use serde::{Deserialize, Serialize};
 use teloxide::prelude::*;
-use teloxide::types::{InputFile, ParseMode};
+use teloxide::types::{
+    BotCommand, BotCommandScope, InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult,
+    InlineQueryResultArticle, InlineQueryResultCachedSticker, InputFile, InputMessageContent,
+    InputMessageContentText, Me, MessageEntityKind, ParseMode, Sticker,
+};
 use teloxide::utils::command::BotCommands;
-use zip::ZipWriter;
 
+use crate::archive::ArchiveFormat;
+use crate::convert::OutputFormat;
+use crate::export::{ExportOptions, Exporter, PackExport};
+use crate::groups::Groups;
+use crate::i18n::{self, Catalogs, Locale};
+use crate::jobs::Owner;
 use crate::limiter;
-use crate::util::export_single_sticker;
+use crate::observability::metrics::metrics;
+use crate::settings::{Settings, UserSettings, FILENAME_TEMPLATES, FRAME_RATES, WIDTHS};
+use crate::storage::StateStorage;
+use crate::util::{
+    edit_status, is_sticker_set_name, parse_sticker_set_link, parse_sticker_set_names,
+};
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub enum State {
     #[default]
     Start,
-    SingleExport,
-    PackExport,
+    SingleExport {
+        format: OutputFormat,
+    },
+    PackExport {
+        format: OutputFormat,
+        archive: ArchiveFormat,
+    },
 }
 
 #[derive(Clone, Debug, BotCommands)]
 #[command(rename_rule = "lowercase", description = "Basic commands")]
 pub enum BasicCommand {
     #[command(description = "Display a brief introduction to the bot")]
-    Start,
+    Start(String),
     #[command(
         rename = "help",
         description = "Display command list and usage information"
     )]
     Help,
-    #[command(rename = "single", description = "Start single sticker export mode")]
-    SingleExport,
-    #[command(rename = "pack", description = "Start pack export mode")]
-    PackExport,
+    #[command(
+        rename = "single",
+        description = "Start single sticker export mode, optionally with an output format"
+    )]
+    SingleExport(String),
+    #[command(
+        rename = "pack",
+        description = "Start pack export mode, optionally with an output and archive format"
+    )]
+    PackExport(String),
+    #[command(description = "Change your export preferences")]
+    Settings,
 }
 
-/// Handle the `/start` command, which provides the user with a brief introduction to the bot.
-#[tracing::instrument]
-pub async fn handle_start(bot: Bot, msg: Message) -> anyhow::Result<()> {
-    bot.send_message(
-        msg.chat.id,
-        r#"
-        I am a sticker export bot that can help you export a sticker or an entire sticker pack.
-        You can use the following commands to enter different modes:
+#[derive(Clone, Debug, BotCommands)]
+#[command(rename_rule = "lowercase", description = "Group commands")]
+pub enum GroupCommand {
+    #[command(
+        rename = "export",
+        description = "Export the sticker or custom emoji in the replied message, optionally with an output format"
+    )]
+    Export(String),
+    #[command(
+        rename = "exportpack",
+        description = "Export the pack of the sticker in the replied message, optionally with an output and archive format"
+    )]
+    ExportPack(String),
+    #[command(
+        rename = "enableexport",
+        description = "Allow exports in this group (administrators only)"
+    )]
+    EnableExport,
+    #[command(
+        rename = "disableexport",
+        description = "Disallow exports in this group (administrators only)"
+    )]
+    DisableExport,
+    #[command(
+        rename = "cancel",
+        description = "Cancel your pack exports in this group"
+    )]
+    Cancel,
+}
+
+/// Register the commands with Telegram, with their descriptions in every supported language.
+pub async fn set_commands(bot: &Bot, catalogs: &Arc<Catalogs>) -> anyhow::Result<()> {
+    // `/cancel` is matched as text, as it works in every mode
+    let private_commands = BasicCommand::bot_commands()
+        .into_iter()
+        .chain([BotCommand::new("cancel", "")])
+        .collect::<Vec<_>>();
+    let group_commands = GroupCommand::bot_commands();
+
+    for language in i18n::languages() {
+        let locale = catalogs.locale(Some(language));
+        let localize = |commands: &[BotCommand]| {
+            commands
+                .iter()
+                .map(|command| {
+                    let name = command.command.trim_start_matches('/');
+                    BotCommand::new(name, locale.text(&format!("command-{}", name)))
+                })
+                .collect::<Vec<_>>()
+        };
 
-        /single - Export a single sticker
-        /pack - Export an entire sticker pack
+        for (commands, scope) in [
+            (&private_commands, BotCommandScope::AllPrivateChats),
+            (&group_commands, BotCommandScope::AllGroupChats),
+        ] {
+            let mut request = bot.set_my_commands(localize(commands)).scope(scope);
+            // English also serves the languages without a catalog
+            if language != "en" {
+                request = request.language_code(language);
+            }
 
-        You can also use the /cancel command to cancel the current operation.
+            request
+                .send()
+                .await
+                .with_context(|| format!("Failed to set the commands in {}", language))?;
+        }
+    }
 
-        This bot is open source. You can find the source code on <a href="https://github.com/AH-dark/telegram-sticker-export-bot">AH-dark/telegram-sticker-export-bot</a>. If you have any questions or suggestions, please feel free to open an issue or pull request. If you like this bot, please give it a star. Thank you!
-        "#.split('\n').map(|s| s.trim()).collect::<Vec<_>>().join("\n"),
+    Ok(())
+}
+
+/// Parse the optional output format argument of the export commands.
+fn parse_format_argument(args: &str, default: OutputFormat) -> anyhow::Result<OutputFormat> {
+    match args.trim() {
+        "" => Ok(default),
+        args => args.parse(),
+    }
+}
+
+/// Parse the optional arguments of the `/pack` command: the output format and the archive
+/// format, in any order, followed by sticker pack links or names to export right away.
+///
+/// Only the leading arguments are taken as formats, so that packs named like a format
+/// (e.g. `/pack gif zip gif`) can still be exported by name.
+fn parse_pack_arguments(
+    args: &str,
+    default_format: OutputFormat,
+    default_archive: ArchiveFormat,
+) -> anyhow::Result<(OutputFormat, ArchiveFormat, Vec<String>)> {
+    let mut format = None;
+    let mut archive = None;
+    let mut set_names = Vec::new();
+
+    for arg in args.split_whitespace() {
+        if set_names.is_empty() {
+            if format.is_none() {
+                if let Ok(parsed) = arg.parse() {
+                    format = Some(parsed);
+                    continue;
+                }
+            }
+            if archive.is_none() {
+                if let Ok(parsed) = arg.parse() {
+                    archive = Some(parsed);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(name) = parse_sticker_set_link(arg) {
+            set_names.push(name);
+        } else if is_sticker_set_name(arg) {
+            set_names.push(arg.to_string());
+        } else {
+            return Err(anyhow::anyhow!("Unknown argument: {}", arg));
+        }
+    }
+
+    Ok((
+        format.unwrap_or(default_format),
+        archive.unwrap_or(default_archive),
+        set_names,
+    ))
+}
+
+/// Build the inline keyboard for choosing the output format, marking the selected one.
+fn format_keyboard(selected: OutputFormat) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(OutputFormat::ALL.chunks(3).map(|row| {
+        row.iter()
+            .map(|format| {
+                let label = if *format == selected {
+                    format!("✅ {}", format)
+                } else {
+                    format.to_string()
+                };
+
+                InlineKeyboardButton::callback(label, format!("format:{}", format.name()))
+            })
+            .collect::<Vec<_>>()
+    }))
+}
+
+/// Reply with the list of available formats after an invalid format argument.
+async fn reply_unknown_format(bot: &Bot, message: &Message, locale: &Locale) -> anyhow::Result<()> {
+    let formats = OutputFormat::ALL
+        .iter()
+        .map(|format| format.name())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let archives = ArchiveFormat::ALL
+        .iter()
+        .map(|format| format.name())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    bot.send_message(
+        message.chat.id,
+        locale.text_with(
+            "unknown-format",
+            [("formats", formats.into()), ("archives", archives.into())],
+        ),
     )
+    .reply_to_message_id(message.id)
+    .send()
+    .await?;
+
+    Ok(())
+}
+
+/// Build the `/start` payload of a deep link that exports a sticker set, see [`parse_deep_link`].
+fn deep_link_payload(set_name: &str, archive: ArchiveFormat) -> String {
+    // deep link payloads are limited to 64 letters, digits, `_` and `-`
+    let payload = format!("{}-{}", set_name, archive.name().replace('.', ""));
+    if payload.len() <= 64 {
+        payload
+    } else {
+        set_name.to_string()
+    }
+}
+
+/// Parse a `<set name>[-<archive format>]` deep link payload.
+fn parse_deep_link(payload: &str) -> Option<(String, Option<ArchiveFormat>)> {
+    let (set_name, archive) = match payload.split_once('-') {
+        Some((set_name, archive)) => (set_name, Some(archive.parse().ok()?)),
+        None => (payload, None),
+    };
+
+    is_sticker_set_name(set_name).then(|| (set_name.to_string(), archive))
+}
+
+/// Deep link into the private chat with the bot that exports a sticker set.
+fn export_deep_link(me: &Me, set_name: &str, archive: ArchiveFormat) -> reqwest::Url {
+    let mut url = me.tme_url();
+    url.query_pairs_mut()
+        .append_pair("start", &deep_link_payload(set_name, archive));
+    url
+}
+
+/// Handle the `/start` command, which provides the user with a brief introduction to the bot.
+///
+/// Deep links from inline mode carry a sticker set to export right away, leaving the current mode.
+#[tracing::instrument(skip(dialogue))]
+pub async fn handle_start(
+    bot: Bot,
+    msg: Message,
+    payload: String,
+    dialogue: Dialogue<State, StateStorage>,
+    settings: Arc<Settings>,
+    rate_limiter: Arc<limiter::Limiter<i64>>,
+    exporter: Arc<Exporter>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(msg.from()).await?;
+    if let Some((set_name, archive)) = parse_deep_link(payload.trim()) {
+        dialogue
+            .reset()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to reset dialogue: {}", e))?;
+
+        let mut options = settings.options(msg.from()).await?;
+        options.archive = archive.unwrap_or(options.archive);
+        return export_packs_now(
+            &bot,
+            &msg,
+            &rate_limiter,
+            &exporter,
+            vec![set_name],
+            options,
+            &locale,
+        )
+        .await;
+    }
+
+    bot.send_message(msg.chat.id, locale.text("start"))
         .parse_mode(ParseMode::Html)
         .send()
         .await?;
@@ -63,37 +303,127 @@ pub async fn handle_start(bot: Bot, msg: Message) -> anyhow::Result<()> {
 
 /// Handle the `/help` command, which provides the user with a list of available commands and their descriptions.
 #[tracing::instrument]
-pub async fn handle_help(bot: Bot, msg: Message) -> anyhow::Result<()> {
-    bot.send_message(
-        msg.chat.id,
-        r#"
-        <b>Available commands:</b>
-        
-        /start - Display a brief introduction to the bot
-        /help - Display command list and usage information
-        /single - Start single sticker export mode
-        /pack - Start pack export mode
-        /cancel - Cancel the current operation
-        "#
-        .trim()
-        .split('\n')
-        .map(|s| s.trim())
-        .collect::<Vec<_>>()
-        .join("\n"),
-    )
-    .parse_mode(ParseMode::Html)
-    .send()
-    .await?;
+pub async fn handle_help(bot: Bot, msg: Message, settings: Arc<Settings>) -> anyhow::Result<()> {
+    let locale = settings.locale(msg.from()).await?;
+
+    bot.send_message(msg.chat.id, locale.text("help"))
+        .parse_mode(ParseMode::Html)
+        .send()
+        .await?;
 
     Ok(())
 }
 
-/// Handle the `/cancel` command, which allows the user to cancel the current operation.
+/// Maximum number of sticker previews in inline query results.
+const INLINE_PREVIEW_LIMIT: usize = 20;
+
+/// Handle inline queries with a sticker pack link or name, which offer to export the pack
+/// through a deep link into the private chat, along with a preview of its stickers.
 #[tracing::instrument]
+pub async fn handle_inline_query(
+    bot: Bot,
+    query: InlineQuery,
+    me: Me,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let sticker_set = match parse_sticker_set_names(&query.query).into_iter().next() {
+        Some(set_name) => bot.get_sticker_set(set_name).await.ok(),
+        None => None,
+    };
+
+    let sticker_set = match sticker_set {
+        Some(sticker_set) => sticker_set,
+        None => {
+            bot.answer_inline_query(query.id, Vec::<InlineQueryResult>::new())
+                .cache_time(0)
+                .send()
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let link_kind = if sticker_set.is_custom_emoji() {
+        "addemoji"
+    } else {
+        "addstickers"
+    };
+    let pack_message = InputMessageContent::Text(InputMessageContentText::new(format!(
+        "{}: https://t.me/{}/{}",
+        sticker_set.title, link_kind, sticker_set.name
+    )));
+
+    // the user's default archive format goes first
+    let default_archive = settings.options(Some(&query.from)).await?.archive;
+    let locale = settings.locale(Some(&query.from)).await?;
+    let description = locale.text_with(
+        "inline-description",
+        [
+            ("title", sticker_set.title.as_str().into()),
+            ("count", sticker_set.stickers.len().into()),
+        ],
+    );
+    let archive_formats = std::iter::once(default_archive).chain(
+        ArchiveFormat::ALL
+            .into_iter()
+            .filter(|archive| *archive != default_archive),
+    );
+
+    let mut results = archive_formats
+        .map(|archive| {
+            InlineQueryResult::Article(
+                InlineQueryResultArticle::new(
+                    format!("export:{}", archive.name()),
+                    locale.text_with("inline-export", [("archive", archive.to_string().into())]),
+                    pack_message.clone(),
+                )
+                .description(description.clone())
+                .reply_markup(InlineKeyboardMarkup::new([[
+                    InlineKeyboardButton::url(
+                        locale
+                            .text_with("inline-export", [("archive", archive.to_string().into())]),
+                        export_deep_link(&me, &sticker_set.name, archive),
+                    ),
+                ]])),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    results.extend(
+        sticker_set
+            .stickers
+            .iter()
+            .take(INLINE_PREVIEW_LIMIT)
+            .map(|sticker| {
+                InlineQueryResult::CachedSticker(InlineQueryResultCachedSticker::new(
+                    format!("sticker:{}", sticker.file.unique_id),
+                    sticker.file.id.clone(),
+                ))
+            }),
+    );
+
+    bot.answer_inline_query(query.id, results)
+        // the results depend on the user's settings and language
+        .is_personal(true)
+        .switch_pm_text(locale.text_with(
+            "inline-switch-pm",
+            [("title", sticker_set.title.as_str().into())],
+        ))
+        .switch_pm_parameter(deep_link_payload(&sticker_set.name, default_archive))
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+/// Handle the `/cancel` command, which allows the user to cancel the current operation
+/// and the pack exports they started in the chat.
+#[tracing::instrument(skip(dialogue))]
 pub async fn handle_cancel(
     bot: Bot,
     update: Update,
-    dialogue: Dialogue<State, InMemStorage<State>>,
+    dialogue: Dialogue<State, StateStorage>,
+    settings: Arc<Settings>,
+    exporter: Arc<Exporter>,
 ) -> anyhow::Result<()> {
     let chat = match update.chat() {
         Some(chat) => chat,
@@ -106,74 +436,512 @@ pub async fn handle_cancel(
         .await
         .map_err(|e| anyhow::anyhow!("Failed to reset dialogue: {}", e))?;
 
-    bot.send_message(chat.id, "Operation canceled.")
+    let locale = settings.locale(update.user()).await?;
+    if let Some(user) = update.user() {
+        cancel_jobs(&bot, &exporter, chat.id, Owner::User(user.id), &locale).await;
+    }
+
+    bot.send_message(chat.id, locale.text("canceled"))
         .send()
         .await?;
 
     Ok(())
 }
 
-/// Handle the `/single` command, which allows the user to export a single sticker.
+/// Cancel the pack exports `owner` started in a chat, marking their status messages as canceled.
+async fn cancel_jobs(bot: &Bot, exporter: &Exporter, chat: ChatId, owner: Owner, locale: &Locale) {
+    for job in exporter.jobs.cancel_all(chat, owner) {
+        // the job is canceled either way, e.g. when its status message was deleted
+        if let Err(e) = bot
+            .edit_message_text(
+                job.status.chat.id,
+                job.status.id,
+                locale.text_with("job-canceled", [("id", job.id.into())]),
+            )
+            .send()
+            .await
+        {
+            log::warn!("Failed to mark export job {} as canceled: {}", job.id, e);
+        }
+    }
+}
+
+/// Whether `user` is an administrator of `chat`, `false` if that can't be looked up.
+async fn is_chat_admin(bot: &Bot, chat: ChatId, user: UserId) -> bool {
+    match bot.get_chat_member(chat, user).send().await {
+        Ok(member) => member.is_privileged(),
+        Err(e) => {
+            log::warn!("Failed to get chat member: {}", e);
+            false
+        }
+    }
+}
+
+/// Handle the cancel button of queued and running pack exports.
 #[tracing::instrument]
+pub async fn handle_cancel_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    exporter: Arc<Exporter>,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(Some(&query.from)).await?;
+    let id = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("cancel:"))
+        .and_then(|id| id.parse().ok());
+
+    let canceled = match id {
+        Some(id) => {
+            // jobs requested on behalf of a chat can be canceled by its administrators
+            let owner = match exporter.jobs.owner(id) {
+                Some(Owner::Chat(chat)) if is_chat_admin(&bot, chat, query.from.id).await => {
+                    Owner::Chat(chat)
+                }
+                _ => Owner::User(query.from.id),
+            };
+            exporter.jobs.cancel(id, owner)
+        }
+        None => None,
+    };
+
+    let job = match canceled {
+        Some(job) => job,
+        None => {
+            bot.answer_callback_query(query.id)
+                .text(locale.text("job-not-found"))
+                .send()
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let text = locale.text_with("job-canceled", [("id", job.id.into())]);
+    bot.answer_callback_query(query.id)
+        .text(text.clone())
+        .send()
+        .await?;
+    bot.edit_message_text(job.status.chat.id, job.status.id, text)
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+/// Handle the `/single` command, which allows the user to export a single sticker.
+#[tracing::instrument(skip(dialogue))]
 pub async fn handle_single_export(
     bot: Bot,
     message: Message,
-    dialogue: Dialogue<State, InMemStorage<State>>,
+    dialogue: Dialogue<State, StateStorage>,
+    args: String,
+    settings: Arc<Settings>,
 ) -> anyhow::Result<()> {
+    let default_format = settings.options(message.from()).await?.format;
+    let locale = settings.locale(message.from()).await?;
+    let format = match parse_format_argument(&args, default_format) {
+        Ok(format) => format,
+        Err(_) => return reply_unknown_format(&bot, &message, &locale).await,
+    };
+
     // Update the dialogue state
     dialogue
-        .update(State::SingleExport)
+        .update(State::SingleExport { format })
         .await
         .map_err(|e| anyhow::anyhow!("Failed to update state: {}", e))?;
 
     // Reply to the user
     bot.send_message(
         message.chat.id,
-        "Single export mode, please send me stickers.",
+        locale.text_with("single-mode", [("format", format.to_string().into())]),
     )
     .reply_to_message_id(message.id)
+    .reply_markup(format_keyboard(format))
     .send()
     .await?;
 
     Ok(())
 }
 
-/// Handle the `/pack` command, which allows the user to export a single sticker.
-#[tracing::instrument]
+/// Handle the `/pack` command, which allows the user to export an entire sticker pack.
+///
+/// With sticker pack links or names as arguments, the packs are exported right away
+/// without entering pack export mode.
+#[tracing::instrument(skip(dialogue))]
 pub async fn handle_pack_export(
     bot: Bot,
     message: Message,
-    dialogue: Dialogue<State, InMemStorage<State>>,
+    dialogue: Dialogue<State, StateStorage>,
+    args: String,
+    settings: Arc<Settings>,
+    rate_limiter: Arc<limiter::Limiter<i64>>,
+    exporter: Arc<Exporter>,
 ) -> anyhow::Result<()> {
+    let options = settings.options(message.from()).await?;
+    let locale = settings.locale(message.from()).await?;
+    let (format, archive, set_names) =
+        match parse_pack_arguments(&args, options.format, options.archive) {
+            Ok(arguments) => arguments,
+            Err(_) => return reply_unknown_format(&bot, &message, &locale).await,
+        };
+
+    if !set_names.is_empty() {
+        return export_packs_now(
+            &bot,
+            &message,
+            &rate_limiter,
+            &exporter,
+            set_names,
+            ExportOptions {
+                format,
+                archive,
+                ..options
+            },
+            &locale,
+        )
+        .await;
+    }
+
     // Update the dialogue state
     dialogue
-        .update(State::PackExport)
+        .update(State::PackExport { format, archive })
         .await
         .map_err(|e| anyhow::anyhow!("Failed to update state: {}", e))?;
 
     // Reply to the user
     bot.send_message(
         message.chat.id,
-        "Pack export mode, please send me stickers from the sticker pack you want to export.",
+        locale.text_with(
+            "pack-mode",
+            [
+                ("format", format.to_string().into()),
+                ("archive", archive.to_string().into()),
+            ],
+        ),
     )
     .reply_to_message_id(message.id)
+    .reply_markup(format_keyboard(format))
     .send()
     .await?;
 
     Ok(())
 }
 
-/// Handle the `/single` and `/pack` commands, which allow the user to export a single sticker or an entire sticker pack.
+/// Handle the output format buttons attached to the export mode messages.
+#[tracing::instrument(skip(dialogue))]
+pub async fn handle_format_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    dialogue: Dialogue<State, StateStorage>,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let format = match query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("format:"))
+        .and_then(|name| name.parse::<OutputFormat>().ok())
+    {
+        Some(format) => format,
+        None => {
+            bot.answer_callback_query(query.id).send().await?;
+            return Ok(());
+        }
+    };
+
+    let state = match dialogue
+        .get_or_default()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to get state: {}", e))?
+    {
+        State::SingleExport { .. } => State::SingleExport { format },
+        State::PackExport { archive, .. } => State::PackExport { format, archive },
+        State::Start => {
+            let locale = settings.locale(Some(&query.from)).await?;
+            bot.answer_callback_query(query.id)
+                .text(locale.text("choose-mode-first"))
+                .send()
+                .await?;
+            return Ok(());
+        }
+    };
+
+    dialogue
+        .update(state)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to update state: {}", e))?;
+
+    let locale = settings.locale(Some(&query.from)).await?;
+    bot.answer_callback_query(query.id)
+        .text(locale.text_with("format-set", [("format", format.to_string().into())]))
+        .send()
+        .await?;
+
+    if let Some(message) = query.message {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(format_keyboard(format))
+            .send()
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Keys of the settings edited with `/settings`, their labels are the `settings-<key>` messages.
+const SETTINGS: [&str; 7] = [
+    "format", "archive", "template", "width", "fps", "manifest", "language",
+];
+
+/// Build the `/settings` keyboard, one button per setting showing its current value.
+fn settings_keyboard(
+    settings: &UserSettings,
+    defaults: &ExportOptions,
+    locale: &Locale,
+) -> InlineKeyboardMarkup {
+    let options = settings.resolve(defaults);
+
+    InlineKeyboardMarkup::new(SETTINGS.map(|key| {
+        let value = match key {
+            "format" => options.format.to_string(),
+            "archive" => options.archive.to_string(),
+            "template" => options.filename_template.to_string(),
+            "width" => format!("{}px", options.animation.width),
+            "fps" => format!("{} fps", options.animation.fps),
+            "manifest" if options.manifest => locale.text("settings-on"),
+            "manifest" => locale.text("settings-off"),
+            // the locale is the chosen language, if there is one
+            _ if settings.language.is_some() => locale.text("language-name"),
+            _ => locale.text("settings-language-auto"),
+        };
+
+        // the manifest is toggled right away, the other settings list their choices
+        let data = match key {
+            "manifest" if options.manifest => "settings:manifest:off".to_string(),
+            "manifest" => "settings:manifest:on".to_string(),
+            key => format!("settings:{}", key),
+        };
+
+        [InlineKeyboardButton::callback(
+            format!("{}: {}", locale.text(&format!("settings-{}", key)), value),
+            data,
+        )]
+    }))
+}
+
+/// Build the keyboard listing the choices of a setting, marking the selected one.
+fn setting_choices_keyboard(
+    key: &str,
+    settings: &UserSettings,
+    defaults: &ExportOptions,
+    locale: &Locale,
+) -> Option<InlineKeyboardMarkup> {
+    let default = |value: String| {
+        (
+            locale.text_with("settings-default", [("value", value.into())]),
+            "default".to_string(),
+        )
+    };
+
+    let choices = match key {
+        "format" => std::iter::once(default(defaults.format.to_string()))
+            .chain(
+                OutputFormat::ALL
+                    .iter()
+                    .map(|format| (format.to_string(), format.name().to_string())),
+            )
+            .collect::<Vec<_>>(),
+        "archive" => std::iter::once(default(defaults.archive.to_string()))
+            .chain(
+                ArchiveFormat::ALL
+                    .iter()
+                    .map(|archive| (archive.to_string(), archive.name().to_string())),
+            )
+            .collect(),
+        "template" => std::iter::once(default(defaults.filename_template.to_string()))
+            .chain(
+                FILENAME_TEMPLATES
+                    .iter()
+                    .map(|template| (template.to_string(), template.to_string())),
+            )
+            .collect(),
+        "width" => WIDTHS
+            .iter()
+            .map(|width| (format!("{}px", width), width.to_string()))
+            .collect(),
+        "fps" => FRAME_RATES
+            .iter()
+            .map(|fps| (format!("{} fps", fps), fps.to_string()))
+            .collect(),
+        "language" => {
+            std::iter::once((locale.text("settings-language-auto"), "default".to_string()))
+                .chain(i18n::languages().map(|language| {
+                    (
+                        locale.in_language(language).text("language-name"),
+                        language.to_string(),
+                    )
+                }))
+                .collect()
+        }
+        _ => return None,
+    };
+
+    let selected = settings.value(key)?;
+    let mut keyboard = InlineKeyboardMarkup::new(choices.chunks(2).map(|row| {
+        row.iter()
+            .map(|(label, value)| {
+                let label = if *value == selected {
+                    format!("✅ {}", label)
+                } else {
+                    label.clone()
+                };
+
+                InlineKeyboardButton::callback(label, format!("settings:{}:{}", key, value))
+            })
+            .collect::<Vec<_>>()
+    }));
+    keyboard = keyboard.append_row([InlineKeyboardButton::callback(
+        locale.text("settings-back"),
+        "settings:back",
+    )]);
+
+    Some(keyboard)
+}
+
+/// Handle the `/settings` command, which shows the user's export preferences with buttons to change them.
 #[tracing::instrument]
-pub async fn handle_export_sticker(
+pub async fn handle_settings(
     bot: Bot,
     message: Message,
-    dialogue: Dialogue<State, InMemStorage<State>>,
-    rate_limiter: Arc<limiter::Limiter<i64>>,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let user = message.from().context("No user found in the message")?;
+    let user_settings = settings.get(user.id).await?;
+    let locale = settings.locale(Some(user)).await?;
+
+    bot.send_message(message.chat.id, locale.text("settings-intro"))
+        .reply_to_message_id(message.id)
+        .reply_markup(settings_keyboard(
+            &user_settings,
+            &settings.defaults,
+            &locale,
+        ))
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+/// Handle the buttons of the `/settings` message: `settings:<key>` lists the choices of a setting,
+/// `settings:<key>:<value>` changes it and `settings:back` returns to the overview.
+#[tracing::instrument]
+pub async fn handle_settings_callback(
+    bot: Bot,
+    query: CallbackQuery,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let data = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("settings:"))
+        .unwrap_or_default();
+    let mut user_settings = settings.get(query.from.id).await?;
+
+    if let Some((key, value)) = data.split_once(':') {
+        if let Err(e) = user_settings.update(key, value) {
+            log::warn!("Invalid settings callback: {:#}", e);
+            bot.answer_callback_query(query.id).send().await?;
+            return Ok(());
+        }
+
+        settings.set(query.from.id, user_settings.clone()).await?;
+    }
+
+    // loaded after the change, which may have been the language
+    let locale = settings.locale(Some(&query.from)).await?;
+    // changed settings and `back` return to the overview
+    let keyboard = setting_choices_keyboard(data, &user_settings, &settings.defaults, &locale)
+        .unwrap_or_else(|| settings_keyboard(&user_settings, &settings.defaults, &locale));
+
+    bot.answer_callback_query(query.id).send().await?;
+
+    if let Some(message) = query.message {
+        bot.edit_message_text(message.chat.id, message.id, locale.text("settings-intro"))
+            .reply_markup(keyboard)
+            .send()
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Collect the stickers of a message: the sticker itself, or the custom emoji in its text.
+async fn message_stickers(bot: &Bot, message: &Message) -> anyhow::Result<Vec<Sticker>> {
+    if let Some(sticker) = message.sticker() {
+        return Ok(vec![sticker.clone()]);
+    }
+
+    let mut custom_emoji_ids = Vec::new();
+    for entity in message
+        .entities()
+        .or_else(|| message.caption_entities())
+        .unwrap_or_default()
+    {
+        if let MessageEntityKind::CustomEmoji { custom_emoji_id } = &entity.kind {
+            if !custom_emoji_ids.contains(custom_emoji_id) {
+                custom_emoji_ids.push(custom_emoji_id.clone());
+            }
+        }
+    }
+
+    if custom_emoji_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    bot.get_custom_emoji_stickers(custom_emoji_ids)
+        .send()
+        .await
+        .context("Failed to get custom emoji stickers")
+}
+
+/// Send each part of a pack export as a document, the summary goes with the last part.
+async fn send_pack_export(
+    bot: &Bot,
+    message: &Message,
+    export: &PackExport,
+    locale: &Locale,
+) -> anyhow::Result<()> {
+    let parts_len = export.parts.paths.len();
+
+    for (i, (filename, path)) in export.files().enumerate() {
+        let mut request = bot
+            .send_document(message.chat.id, InputFile::file(path).file_name(filename))
+            .reply_to_message_id(message.id);
+
+        if i + 1 == parts_len {
+            request = request.caption(export.summary(locale));
+        }
+
+        request.send().await?;
+    }
+
+    Ok(())
+}
+
+/// Check the rate limit of the chat, replying to the message when it is exceeded.
+async fn check_rate_limit(
+    bot: &Bot,
+    message: &Message,
+    rate_limiter: &limiter::Limiter<i64>,
+    locale: &Locale,
 ) -> anyhow::Result<()> {
-    // Check the rate limit
     if !rate_limiter.check(message.chat.id.0) {
-        bot.send_message(message.chat.id, "Rate limit exceeded.")
+        metrics().record_rate_limited(if message.chat.is_private() {
+            "private"
+        } else {
+            "group"
+        });
+
+        bot.send_message(message.chat.id, locale.text("rate-limited"))
             .reply_to_message_id(message.id)
             .send()
             .await?;
@@ -181,191 +949,506 @@ pub async fn handle_export_sticker(
         return Err(anyhow::anyhow!("Rate limit exceeded"));
     }
 
-    // Check if the message contains a sticker
-    let sticker = match message.sticker() {
-        Some(sticker) => sticker,
-        None => {
+    Ok(())
+}
+
+/// Key of the conversion pool budget of whoever sent the message, the chat if there's no sender.
+fn sender_id(message: &Message) -> i64 {
+    message
+        .from()
+        .map(|user| user.id.0 as i64)
+        .unwrap_or(message.chat.id.0)
+}
+
+/// Export sticker sets by name and send their archives, reporting progress on `waiting_msg`.
+async fn export_sticker_sets(
+    bot: &Bot,
+    message: &Message,
+    exporter: &Exporter,
+    set_names: &[String],
+    options: &ExportOptions,
+    waiting_msg: &Message,
+    locale: &Locale,
+) -> anyhow::Result<()> {
+    for set_name in set_names {
+        // Get the sticker set
+        let sticker_set = bot
+            .get_sticker_set(set_name)
+            .await
+            .with_context(|| format!("Failed to get sticker set {}", set_name))?;
+
+        let export = exporter
+            .export_pack(
+                bot,
+                sender_id(message),
+                &sticker_set,
+                options,
+                waiting_msg,
+                locale,
+            )
+            .await?;
+
+        // update status
+        if let Err(e) = edit_status(bot, waiting_msg, locale.text("uploading")).await {
+            log::warn!("Failed to update the export status: {:#}", e);
+        }
+
+        send_pack_export(bot, message, &export, locale).await?;
+    }
+
+    Ok(())
+}
+
+/// Queue the export of sticker sets, which runs in the background and can be canceled.
+async fn enqueue_pack_export(
+    bot: &Bot,
+    message: &Message,
+    exporter: &Arc<Exporter>,
+    set_names: Vec<String>,
+    options: ExportOptions,
+    locale: &Locale,
+) -> anyhow::Result<()> {
+    if set_names.is_empty() {
+        bot.send_message(message.chat.id, locale.text("send-sticker-from-pack"))
+            .reply_to_message_id(message.id)
+            .send()
+            .await?;
+
+        return Ok(());
+    }
+
+    let job = {
+        let bot = bot.clone();
+        let message = message.clone();
+        let exporter = exporter.clone();
+        let locale = locale.clone();
+        move |status: Message| async move {
+            let result = export_sticker_sets(
+                &bot, &message, &exporter, &set_names, &options, &status, &locale,
+            )
+            .await;
+
+            finish_export(&bot, &message, &status, result, &locale).await
+        }
+    };
+    exporter.jobs.enqueue(bot, message, locale, job).await?;
+
+    Ok(())
+}
+
+/// Export sticker sets outside of pack export mode.
+async fn export_packs_now(
+    bot: &Bot,
+    message: &Message,
+    rate_limiter: &limiter::Limiter<i64>,
+    exporter: &Arc<Exporter>,
+    set_names: Vec<String>,
+    options: ExportOptions,
+    locale: &Locale,
+) -> anyhow::Result<()> {
+    check_rate_limit(bot, message, rate_limiter, locale).await?;
+
+    enqueue_pack_export(bot, message, exporter, set_names, options, locale).await
+}
+
+/// Report the outcome of an export and remove the waiting message.
+async fn finish_export(
+    bot: &Bot,
+    message: &Message,
+    waiting_msg: &Message,
+    result: anyhow::Result<()>,
+    locale: &Locale,
+) -> anyhow::Result<()> {
+    // the error is recorded by whoever receives the result
+    if let Err(e) = &result {
+        let error = format!("{:#}", e);
+        bot.send_message(
+            message.chat.id,
+            locale.text_with("export-failed", [("error", error.into())]),
+        )
+        .reply_to_message_id(message.id)
+        .send()
+        .await?;
+    }
+
+    bot.delete_message(message.chat.id, waiting_msg.id)
+        .send()
+        .await?;
+
+    result
+}
+
+/// Handle the `/single` and `/pack` commands, which allow the user to export a single sticker or an entire sticker pack.
+///
+/// Both modes also accept text messages with custom emoji, which are exported as stickers,
+/// and pack mode accepts sticker pack links and names.
+#[tracing::instrument(skip(dialogue))]
+pub async fn handle_export_sticker(
+    bot: Bot,
+    message: Message,
+    dialogue: Dialogue<State, StateStorage>,
+    rate_limiter: Arc<limiter::Limiter<i64>>,
+    exporter: Arc<Exporter>,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(message.from()).await?;
+
+    // Check the rate limit
+    check_rate_limit(&bot, &message, &rate_limiter, &locale).await?;
+
+    let state = match dialogue.get_or_default().await {
+        Ok(state) => state,
+        Err(e) => {
             bot.send_message(
                 message.chat.id,
-                r#"
-            You need to send me a sticker to export. Please send me a sticker and try again.
-            If you want to quit the current operation, you can use the /cancel command.
-            "#
-                .trim()
-                .split('\n')
-                .map(|s| s.trim())
-                .collect::<Vec<_>>()
-                .join("\n"),
+                locale.text_with("state-error", [("error", e.to_string().into())]),
             )
             .reply_to_message_id(message.id)
             .send()
             .await?;
 
-            return Ok(());
+            return Err(anyhow::anyhow!("Failed to get state: {}", e));
+        }
+    };
+
+    // Check if the message contains a sticker, custom emoji or, in pack mode, sticker pack links
+    let stickers = message_stickers(&bot, &message).await?;
+    let set_names = match state {
+        State::PackExport { .. } if stickers.is_empty() => message
+            .text()
+            .map(parse_sticker_set_names)
+            .unwrap_or_default(),
+        State::PackExport { .. } => {
+            // export every sticker set the stickers come from, once
+            let mut set_names = Vec::new();
+            for set_name in stickers
+                .iter()
+                .filter_map(|sticker| sticker.set_name.as_ref())
+            {
+                if !set_names.contains(set_name) {
+                    set_names.push(set_name.clone());
+                }
+            }
+            set_names
+        }
+        _ => Vec::new(),
+    };
+
+    if stickers.is_empty() && set_names.is_empty() {
+        bot.send_message(message.chat.id, locale.text("send-sticker"))
+            .reply_to_message_id(message.id)
+            .disable_web_page_preview(true)
+            .send()
+            .await?;
+
+        return Ok(());
+    }
+
+    let options = settings.options(message.from()).await?;
+    let format = match state {
+        State::SingleExport { format } => format,
+        State::PackExport { format, archive } => {
+            // packs are exported in the background
+            return enqueue_pack_export(
+                &bot,
+                &message,
+                &exporter,
+                set_names,
+                ExportOptions {
+                    format,
+                    archive,
+                    ..options
+                },
+                &locale,
+            )
+            .await;
+        }
+        State::Start => {
+            unreachable!("Invalid state")
         }
     };
 
     let waiting_msg = bot
-        .send_message(message.chat.id, "Processing...")
+        .send_message(message.chat.id, locale.text("processing"))
         .reply_to_message_id(message.id)
         .send()
         .await?;
 
-    match dialogue.get_or_default().await {
-        Ok(State::SingleExport) => {
-            match export_single_sticker(bot.clone(), sticker).await {
-                Ok((filename, data)) => {
-                    bot.send_document(message.chat.id, InputFile::memory(data).file_name(filename))
-                        .reply_to_message_id(message.id)
-                        .send()
-                        .await?;
-                }
-                Err(e) => {
-                    bot.send_message(message.chat.id, e.to_string())
-                        .reply_to_message_id(message.id)
-                        .send()
-                        .await?;
-
-                    bot.delete_message(message.chat.id, waiting_msg.id)
-                        .send()
-                        .await?;
+    let options = ExportOptions { format, ..options };
+    let result = async {
+        for sticker in &stickers {
+            let (filename, data) = exporter
+                .export_sticker(&bot, sender_id(&message), sticker, &options)
+                .await?;
 
-                    return Err(e);
-                }
-            };
+            bot.send_document(message.chat.id, InputFile::memory(data).file_name(filename))
+                .reply_to_message_id(message.id)
+                .send()
+                .await?;
         }
-        Ok(State::PackExport) => {
-            // check if the sticker is from a sticker pack
-            if sticker.set_name.is_none() {
-                bot.send_message(
-                    message.chat.id,
-                    "Please send me a sticker from a sticker pack.",
-                )
+
+        Ok(())
+    }
+    .await;
+
+    finish_export(&bot, &message, &waiting_msg, result, &locale).await
+}
+
+/// Check that exports are enabled in the group and that the command replies to a message,
+/// returning the replied message.
+async fn group_export_target<'a>(
+    bot: &Bot,
+    message: &'a Message,
+    groups: &Groups,
+    locale: &Locale,
+) -> anyhow::Result<Option<&'a Message>> {
+    if !groups.is_enabled(message.chat.id.0).await? {
+        return Ok(None);
+    }
+
+    match message.reply_to_message() {
+        Some(replied) => Ok(Some(replied)),
+        None => {
+            bot.send_message(message.chat.id, locale.text("group-reply-required"))
                 .reply_to_message_id(message.id)
                 .send()
                 .await?;
 
-                bot.delete_message(message.chat.id, waiting_msg.id)
-                    .send()
-                    .await?;
+            Ok(None)
+        }
+    }
+}
 
-                return Err(anyhow::anyhow!("Invalid sticker pack"));
-            }
+/// Handle the `/export` group command, which exports the sticker or custom emoji in the replied message.
+#[tracing::instrument]
+pub async fn handle_group_export(
+    bot: Bot,
+    message: Message,
+    args: String,
+    settings: Arc<Settings>,
+    groups: Arc<Groups>,
+    exporter: Arc<Exporter>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(message.from()).await?;
+    let options = settings.options(message.from()).await?;
+    let options = match parse_format_argument(&args, options.format) {
+        Ok(format) => ExportOptions { format, ..options },
+        Err(_) => return reply_unknown_format(&bot, &message, &locale).await,
+    };
 
-            // Get the sticker set
-            let sticker_set = bot
-                .get_sticker_set(sticker.set_name.as_ref().unwrap())
-                .await
-                .context("Failed to get sticker set")?;
+    let replied = match group_export_target(&bot, &message, &groups, &locale).await? {
+        Some(replied) => replied,
+        None => return Ok(()),
+    };
 
-            // Get the stickers in the sticker pack
-            let mut futures = FuturesUnordered::new();
-            let stickers_len = sticker_set.stickers.len();
+    let stickers = message_stickers(&bot, replied).await?;
+    if stickers.is_empty() {
+        bot.send_message(message.chat.id, locale.text("group-no-sticker"))
+            .reply_to_message_id(message.id)
+            .send()
+            .await?;
 
-            for sticker in sticker_set.stickers {
-                let bot = bot.clone();
-                futures.push(async move { export_single_sticker(bot, &sticker).await });
-            }
+        return Ok(());
+    }
 
-            let mut sticker_files = Vec::new();
-            let mut downloaded_len = 0;
-
-            while let Some(result) = futures.next().await {
-                match result {
-                    Ok((filename, data)) => {
-                        sticker_files.push((filename, data));
-                        downloaded_len += 1;
-
-                        // Update progress every 5 stickers
-                        if downloaded_len % 5 == 0 || downloaded_len == stickers_len {
-                            bot.edit_message_text(
-                                message.chat.id,
-                                waiting_msg.id,
-                                format!("Downloading... {}/{}", downloaded_len, stickers_len),
-                            )
-                            .send()
-                            .await?;
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to export sticker: {}", e);
-
-                        bot.send_message(message.chat.id, e.to_string())
-                            .reply_to_message_id(message.id)
-                            .send()
-                            .await?;
-
-                        bot.delete_message(message.chat.id, waiting_msg.id)
-                            .send()
-                            .await?;
-
-                        return Err(e);
-                    }
-                }
-            }
+    // Check the rate limit of the group
+    check_rate_limit(&bot, &message, &groups.rate_limiter, &locale).await?;
 
-            // Create a zip archive containing all the stickers
-            let mut buffer = Vec::new();
-            {
-                let mut zip = ZipWriter::new(std::io::Cursor::new(&mut buffer));
-                let options: zip::write::FileOptions<zip::write::ExtendedFileOptions> =
-                    zip::write::FileOptions::default()
-                        .compression_method(zip::CompressionMethod::Deflated)
-                        .unix_permissions(0o755);
-
-                for (i, (filename, data)) in sticker_files.iter().enumerate() {
-                    zip.start_file(filename.to_owned(), options.clone())
-                        .context("Failed to start file in zip archive")?;
-                    zip.write_all(&data)
-                        .context("Failed to write file to zip archive")?;
-
-                    // update status
-                    if i % 5 == 0 || i == stickers_len {
-                        bot.edit_message_text(
-                            message.chat.id,
-                            waiting_msg.id,
-                            format!("Compressing... {}/{}", &i, &stickers_len),
-                        )
-                        .send()
-                        .await
-                        .context("Failed to update status")
-                        .ok();
-                    }
-                }
+    let waiting_msg = bot
+        .send_message(message.chat.id, locale.text("processing"))
+        .reply_to_message_id(message.id)
+        .send()
+        .await?;
 
-                zip.finish().context("Failed to finish zip archive")?;
-            }
+    let result = async {
+        for sticker in &stickers {
+            let (filename, data) = exporter
+                .export_sticker(&bot, sender_id(&message), sticker, &options)
+                .await?;
 
-            // update status
-            bot.edit_message_text(message.chat.id, waiting_msg.id, "Uploading zip archive...")
+            bot.send_document(message.chat.id, InputFile::memory(data).file_name(filename))
+                .reply_to_message_id(message.id)
                 .send()
                 .await?;
+        }
 
-            bot.send_document(
-                message.chat.id,
-                InputFile::memory(buffer).file_name(format!("stickers-{}.zip", &sticker_set.name)),
-            )
-            .reply_to_message_id(message.id)
-            .send()
-            .await?;
+        Ok(())
+    }
+    .await;
+
+    finish_export(&bot, &message, &waiting_msg, result, &locale).await
+}
+
+/// Handle the `/exportpack` group command, which exports the pack of the sticker in the replied
+/// message, or the packs linked in the command arguments.
+#[tracing::instrument]
+pub async fn handle_group_export_pack(
+    bot: Bot,
+    message: Message,
+    args: String,
+    settings: Arc<Settings>,
+    groups: Arc<Groups>,
+    exporter: Arc<Exporter>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(message.from()).await?;
+    let options = settings.options(message.from()).await?;
+    let (format, archive, mut set_names) =
+        match parse_pack_arguments(&args, options.format, options.archive) {
+            Ok(arguments) => arguments,
+            Err(_) => return reply_unknown_format(&bot, &message, &locale).await,
+        };
+
+    if set_names.is_empty() {
+        let replied = match group_export_target(&bot, &message, &groups, &locale).await? {
+            Some(replied) => replied,
+            None => return Ok(()),
+        };
+
+        for sticker in message_stickers(&bot, replied).await? {
+            if let Some(set_name) = sticker.set_name {
+                if !set_names.contains(&set_name) {
+                    set_names.push(set_name);
+                }
+            }
         }
-        Ok(_) => {
-            unreachable!("Invalid state")
+    } else if !groups.is_enabled(message.chat.id.0).await? {
+        return Ok(());
+    }
+
+    export_packs_now(
+        &bot,
+        &message,
+        &groups.rate_limiter,
+        &exporter,
+        set_names,
+        ExportOptions {
+            format,
+            archive,
+            ..options
+        },
+        &locale,
+    )
+    .await
+}
+
+/// Handle the `/cancel` group command, which cancels the pack exports the sender started in the group.
+#[tracing::instrument]
+pub async fn handle_group_cancel(
+    bot: Bot,
+    message: Message,
+    settings: Arc<Settings>,
+    exporter: Arc<Exporter>,
+) -> anyhow::Result<()> {
+    let owner = match Owner::of(&message) {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+
+    let locale = settings.locale(message.from()).await?;
+    cancel_jobs(&bot, &exporter, message.chat.id, owner, &locale).await;
+
+    bot.send_message(message.chat.id, locale.text("canceled"))
+        .reply_to_message_id(message.id)
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+/// Handle the `/enableexport` and `/disableexport` group commands, which are limited to administrators.
+#[tracing::instrument]
+pub async fn handle_group_settings(
+    bot: Bot,
+    message: Message,
+    command: GroupCommand,
+    groups: Arc<Groups>,
+    settings: Arc<Settings>,
+) -> anyhow::Result<()> {
+    let locale = settings.locale(message.from()).await?;
+    let enabled = match command {
+        GroupCommand::EnableExport => true,
+        GroupCommand::DisableExport => false,
+        _ => return Ok(()),
+    };
+
+    // anonymous administrators send messages on behalf of the group
+    let is_admin = if message.sender_chat().map(|chat| chat.id) == Some(message.chat.id) {
+        true
+    } else {
+        match message.from() {
+            Some(user) => bot
+                .get_chat_member(message.chat.id, user.id)
+                .send()
+                .await
+                .context("Failed to get chat member")?
+                .is_privileged(),
+            None => false,
         }
-        Err(e) => {
-            bot.send_message(
-                message.chat.id,
-                format!("Failed to get state, state manager error: {}", e),
-            )
+    };
+
+    if !is_admin {
+        bot.send_message(message.chat.id, locale.text("group-admin-only"))
             .reply_to_message_id(message.id)
             .send()
             .await?;
-        }
+
+        return Ok(());
     }
 
-    bot.delete_message(message.chat.id, waiting_msg.id)
-        .send()
-        .await?;
+    groups.set_enabled(message.chat.id.0, enabled).await?;
+
+    bot.send_message(
+        message.chat.id,
+        locale.text(if enabled {
+            "group-enabled"
+        } else {
+            "group-disabled"
+        }),
+    )
+    .reply_to_message_id(message.id)
+    .send()
+    .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_arguments_take_leading_formats() {
+        let (format, archive, set_names) =
+            parse_pack_arguments("tar.zst apng", OutputFormat::Gif, ArchiveFormat::Zip).unwrap();
+        assert_eq!(format, OutputFormat::Apng);
+        assert_eq!(archive, ArchiveFormat::TarZst);
+        assert!(set_names.is_empty());
+    }
+
+    #[test]
+    fn pack_arguments_export_sets_named_like_formats() {
+        let (format, archive, set_names) =
+            parse_pack_arguments("gif zip gif json", OutputFormat::Png, ArchiveFormat::TarGz)
+                .unwrap();
+        assert_eq!(format, OutputFormat::Gif);
+        assert_eq!(archive, ArchiveFormat::Zip);
+        assert_eq!(set_names, ["gif", "json"]);
+
+        let (format, archive, set_names) = parse_pack_arguments(
+            "https://t.me/addstickers/raw zip",
+            OutputFormat::Png,
+            ArchiveFormat::TarGz,
+        )
+        .unwrap();
+        assert_eq!(format, OutputFormat::Png);
+        assert_eq!(archive, ArchiveFormat::TarGz);
+        assert_eq!(set_names, ["raw", "zip"]);
+    }
+
+    #[test]
+    fn pack_arguments_reject_invalid_names() {
+        assert!(
+            parse_pack_arguments("gif not-a-set", OutputFormat::Png, ArchiveFormat::Zip).is_err()
+        );
+    }
+}
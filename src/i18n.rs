@@ -0,0 +1,129 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+
+/// Message catalogs shipped with the bot, English first as it is the fallback.
+const CATALOGS: [(&str, &str); 3] = [
+    ("en", include_str!("../locales/en/bot.ftl")),
+    ("zh", include_str!("../locales/zh/bot.ftl")),
+    ("ru", include_str!("../locales/ru/bot.ftl")),
+];
+
+/// Codes of the supported languages.
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(code, _)| *code)
+}
+
+/// The parsed message catalogs of all supported languages.
+pub struct Catalogs {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Catalogs {
+    /// Parse the shipped catalogs, which are known to be valid.
+    pub fn load() -> Arc<Self> {
+        let bundles = CATALOGS
+            .iter()
+            .map(|(code, source)| {
+                let resource = FluentResource::try_new(source.to_string())
+                    .unwrap_or_else(|(_, e)| panic!("Invalid `{}` catalog: {:?}", code, e));
+
+                let mut bundle =
+                    FluentBundle::new_concurrent(vec![code.parse().expect("Invalid language")]);
+                // isolation marks around arguments show up in some Telegram clients
+                bundle.set_use_isolating(false);
+                bundle.add_resource(resource).unwrap_or_else(|e| {
+                    panic!("Duplicate messages in `{}` catalog: {:?}", code, e)
+                });
+                bundle
+            })
+            .collect();
+
+        Arc::new(Self { bundles })
+    }
+
+    /// Pick the catalog for a language tag such as `zh-hans`, English if there is none.
+    pub fn locale(self: &Arc<Self>, language_code: Option<&str>) -> Locale {
+        let index = language_code
+            .map(str::to_lowercase)
+            .and_then(|code| {
+                let primary = code.split(['-', '_']).next().unwrap_or_default();
+                languages()
+                    .position(|language| language == code)
+                    .or_else(|| languages().position(|language| language == primary))
+            })
+            .unwrap_or(0);
+
+        Locale {
+            catalogs: self.clone(),
+            index,
+        }
+    }
+}
+
+/// The catalog of one language, falling back to English for missing messages.
+#[derive(Clone)]
+pub struct Locale {
+    catalogs: Arc<Catalogs>,
+    index: usize,
+}
+
+impl Debug for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Locale").field(&self.code()).finish()
+    }
+}
+
+impl Locale {
+    pub fn code(&self) -> &'static str {
+        CATALOGS[self.index].0
+    }
+
+    /// The catalog of another language, see [`Catalogs::locale`].
+    pub fn in_language(&self, language: &str) -> Locale {
+        self.catalogs.locale(Some(language))
+    }
+
+    /// The text of a message without arguments.
+    pub fn text(&self, id: &str) -> String {
+        self.format(id, None)
+    }
+
+    /// The text of a message with the given arguments.
+    pub fn text_with<'a>(
+        &self,
+        id: &str,
+        args: impl IntoIterator<Item = (&'a str, FluentValue<'a>)>,
+    ) -> String {
+        self.format(id, Some(&args.into_iter().collect()))
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        let bundles = [
+            &self.catalogs.bundles[self.index],
+            &self.catalogs.bundles[0],
+        ];
+
+        for bundle in bundles {
+            if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+                let mut errors = Vec::new();
+                let text = bundle.format_pattern(pattern, args, &mut errors);
+                if !errors.is_empty() {
+                    log::warn!(
+                        "Failed to format message {} ({}): {:?}",
+                        id,
+                        self.code(),
+                        errors
+                    );
+                }
+
+                return text.into_owned();
+            }
+        }
+
+        log::warn!("Missing message {}", id);
+        id.to_string()
+    }
+}
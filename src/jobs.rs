@@ -0,0 +1,287 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::AbortHandle;
+
+use crate::i18n::Locale;
+use crate::observability::metrics::metrics;
+use crate::util::edit_status;
+
+/// Queue of pack exports running in the background.
+///
+/// At most `workers` jobs run at once, the others wait in FIFO order and keep their status
+/// message updated with their position. Cancelling a job aborts its task, dropping the export,
+/// which kills its conversions and removes its temporary files.
+#[derive(Debug)]
+pub struct Jobs {
+    slots: Arc<Semaphore>,
+    next_id: AtomicU64,
+    state: Mutex<JobsState>,
+    /// Bumped whenever the queue moves, so waiting jobs report their new position.
+    changed: watch::Sender<u64>,
+}
+
+#[derive(Debug, Default)]
+struct JobsState {
+    /// Jobs waiting for a slot, in queue order.
+    waiting: VecDeque<u64>,
+    jobs: HashMap<u64, Job>,
+}
+
+#[derive(Debug)]
+struct Job {
+    /// Who requested the job, the only one allowed to cancel it.
+    owner: Option<Owner>,
+    status: Message,
+    abort: AbortHandle,
+}
+
+/// The sender of a job request, who owns the job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Owner {
+    User(UserId),
+    /// A chat the request was sent on behalf of, by an anonymous group administrator or a channel.
+    Chat(ChatId),
+}
+
+impl Owner {
+    /// The sender of a message, its sender chat if it was sent on behalf of one.
+    pub fn of(message: &Message) -> Option<Self> {
+        match message.sender_chat() {
+            Some(chat) => Some(Self::Chat(chat.id)),
+            None => message.from().map(|user| Self::User(user.id)),
+        }
+    }
+}
+
+/// A job stopped by [`Jobs::cancel`], with the message that reported its status.
+#[derive(Debug)]
+pub struct CanceledJob {
+    pub id: u64,
+    pub status: Message,
+}
+
+impl Jobs {
+    pub fn new(workers: usize) -> Arc<Self> {
+        Arc::new(Self {
+            slots: Arc::new(Semaphore::new(workers.max(1))),
+            next_id: AtomicU64::new(1),
+            state: Mutex::new(JobsState::default()),
+            changed: watch::channel(0).0,
+        })
+    }
+
+    /// Queue a job requested by `message`, replying with a status message that has a cancel button.
+    ///
+    /// `job` gets the status message once the job leaves the queue, returns the id of the job.
+    pub async fn enqueue<F, Fut>(
+        self: &Arc<Self>,
+        bot: &Bot,
+        message: &Message,
+        locale: &Locale,
+        job: F,
+    ) -> anyhow::Result<u64>
+    where
+        F: FnOnce(Message) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        // the job takes its place in the queue before its status reports it
+        let (id, position) = {
+            let mut state = self.state.lock().unwrap();
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            state.waiting.push_back(id);
+            (id, state.waiting.len())
+        };
+        // leaves the queue if the status message can't be sent
+        let guard = JobGuard {
+            jobs: self.clone(),
+            id,
+        };
+
+        let status = bot
+            .send_message(message.chat.id, queued_text(locale, id, position))
+            .reply_to_message_id(message.id)
+            .reply_markup(InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback(locale.text("job-cancel"), format!("cancel:{}", id)),
+            ]]))
+            .send()
+            .await?;
+
+        let task = {
+            let jobs = self.clone();
+            let bot = bot.clone();
+            let status = status.clone();
+            let locale = locale.clone();
+            async move {
+                let _guard = guard;
+                // the slot is freed once the job ends
+                let _permit = jobs
+                    .wait_for_slot(id, &bot, &status, &locale, position)
+                    .await;
+
+                if let Err(e) = job(status).await {
+                    metrics().record_error(&e);
+                    log::error!("Export job {} failed: {:?}", id, e);
+                }
+            }
+        };
+
+        // the lock keeps the job from finishing before it is registered
+        let mut state = self.state.lock().unwrap();
+        let abort = tokio::spawn(task).abort_handle();
+        state.jobs.insert(
+            id,
+            Job {
+                owner: Owner::of(message),
+                status,
+                abort,
+            },
+        );
+        drop(state);
+        self.notify();
+
+        log::info!("Queued export job {} at position {}", id, position);
+        Ok(id)
+    }
+
+    /// Wait until the job can run, keeping its status message updated with its queue position.
+    async fn wait_for_slot(
+        &self,
+        id: u64,
+        bot: &Bot,
+        status: &Message,
+        locale: &Locale,
+        mut reported: usize,
+    ) -> OwnedSemaphorePermit {
+        let mut changed = self.changed.subscribe();
+        let permit = self.slots.clone().acquire_owned();
+        tokio::pin!(permit);
+
+        let permit = loop {
+            if let Some(position) = self.position(id) {
+                if position != reported {
+                    if let Err(e) =
+                        edit_status(bot, status, queued_text(locale, id, position)).await
+                    {
+                        log::warn!("Failed to update the position of job {}: {:#}", id, e);
+                    }
+                    reported = position;
+                }
+            }
+
+            tokio::select! {
+                // the semaphore is never closed
+                permit = &mut permit => break permit.unwrap(),
+                _ = changed.changed() => {}
+            }
+        };
+
+        self.state
+            .lock()
+            .unwrap()
+            .waiting
+            .retain(|waiting| *waiting != id);
+        self.notify();
+
+        if let Err(e) = edit_status(bot, status, locale.text("processing")).await {
+            log::warn!("Failed to update the status of job {}: {:#}", id, e);
+        }
+
+        permit
+    }
+
+    /// Position of a waiting job in the queue, starting at 1.
+    fn position(&self, id: u64) -> Option<usize> {
+        self.state
+            .lock()
+            .unwrap()
+            .waiting
+            .iter()
+            .position(|waiting| *waiting == id)
+            .map(|index| index + 1)
+    }
+
+    /// Who requested a job, `None` if it has finished or its owner is unknown.
+    pub fn owner(&self, id: u64) -> Option<Owner> {
+        self.state.lock().unwrap().jobs.get(&id)?.owner
+    }
+
+    /// Cancel a job on behalf of `owner`, `None` if it has finished or belongs to someone else.
+    pub fn cancel(&self, id: u64, owner: Owner) -> Option<CanceledJob> {
+        let mut state = self.state.lock().unwrap();
+        if state.jobs.get(&id)?.owner != Some(owner) {
+            return None;
+        }
+
+        let canceled = Self::remove(&mut state, id);
+        drop(state);
+        self.notify();
+
+        canceled
+    }
+
+    /// Cancel every job `owner` owns in a chat.
+    pub fn cancel_all(&self, chat: ChatId, owner: Owner) -> Vec<CanceledJob> {
+        let mut state = self.state.lock().unwrap();
+        let ids = state
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.status.chat.id == chat && job.owner == Some(owner))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let canceled = ids
+            .into_iter()
+            .filter_map(|id| Self::remove(&mut state, id))
+            .collect();
+        drop(state);
+        self.notify();
+
+        canceled
+    }
+
+    /// Abort and forget a job.
+    fn remove(state: &mut JobsState, id: u64) -> Option<CanceledJob> {
+        state.waiting.retain(|waiting| *waiting != id);
+        let job = state.jobs.remove(&id)?;
+        job.abort.abort();
+        log::info!("Canceled export job {}", id);
+
+        Some(CanceledJob {
+            id,
+            status: job.status,
+        })
+    }
+
+    fn notify(&self) {
+        self.changed.send_modify(|version| *version += 1);
+    }
+}
+
+fn queued_text(locale: &Locale, id: u64, position: usize) -> String {
+    locale.text_with(
+        "job-queued",
+        [("id", id.into()), ("position", position.into())],
+    )
+}
+
+/// Forgets a job once its task ends, whether it finished or was aborted.
+struct JobGuard {
+    jobs: Arc<Jobs>,
+    id: u64,
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let mut state = self.jobs.state.lock().unwrap();
+        state.waiting.retain(|waiting| *waiting != self.id);
+        state.jobs.remove(&self.id);
+        drop(state);
+        self.jobs.notify();
+    }
+}
@@ -1,9 +1,9 @@
 use std::hash::Hash;
 use std::sync::Arc;
 
-use governor::{Quota, RateLimiter};
 use governor::clock::DefaultClock;
 use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
 
 #[derive(Debug)]
 pub struct Limiter<K: Hash + Clone + Eq>(RateLimiter<K, DefaultKeyedStateStore<K>, DefaultClock>);
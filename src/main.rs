@@ -1,98 +1,243 @@
 use std::sync::Arc;
 
-use governor::{clock, Quota};
-use teloxide::dispatching::dialogue::InMemStorage;
+use governor::clock;
 use teloxide::prelude::*;
 
+use crate::config::Command;
+use crate::convert::transcoder::Transcoder;
+use crate::export::Exporter;
+use crate::groups::Groups;
 use crate::handlers::*;
+use crate::i18n::Catalogs;
+use crate::jobs::Jobs;
 use crate::limiter::Limiter;
-use crate::util::env_or_default;
+use crate::observability::metrics::{init_metrics, metrics};
+use crate::observability::server::Readiness;
+use crate::pool::ConversionPool;
+use crate::settings::Settings;
+use crate::storage::StateStorage;
 
+pub(crate) mod archive;
+pub(crate) mod config;
+pub(crate) mod convert;
+pub(crate) mod export;
+pub(crate) mod groups;
 pub(crate) mod handlers;
+pub(crate) mod i18n;
+pub(crate) mod jobs;
 pub(crate) mod limiter;
+pub(crate) mod manifest;
+pub(crate) mod naming;
 pub(crate) mod observability;
+pub(crate) mod pool;
+pub(crate) mod settings;
+pub(crate) mod storage;
 pub(crate) mod util;
+pub(crate) mod webhook;
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
     dotenv::dotenv().ok();
-    observability::tracing::init_tracer();
+    let config = match config::load() {
+        Ok(Command::Run(config)) => *config,
+        Ok(Command::PrintConfig(values, error)) => {
+            print!("{}", values);
+            if let Some(e) = error {
+                eprintln!("Invalid configuration: {:#}", e);
+                std::process::exit(2);
+            }
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", config::usage());
+            return;
+        }
+        Err(e) => {
+            // logging isn't set up yet
+            eprintln!("Invalid configuration: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+
+    observability::tracing::init_tracer(&config.telemetry_exporter, config.sample_rate);
+    let registry = init_metrics(&config.telemetry_exporter);
+    let readiness = Arc::new(Readiness::default());
+    observability::server::serve(config.monitoring_address, registry, readiness.clone())
+        .expect("Failed to start the monitoring server");
 
     log::info!("Starting sticker exporter bot...");
 
-    let bot = Bot::from_env().set_api_url(
-        reqwest::Url::parse(
-            env_or_default("TELEGRAM_API_URL", "https://api.telegram.org").as_str(),
-        )
-        .unwrap(),
-    );
+    let bot = Bot::with_client(config.teloxide_token, teloxide::net::client_from_env())
+        .set_api_url(config.telegram_api_url);
 
     let clock = clock::DefaultClock::default();
-    let rate_limiter: Arc<Limiter<i64>> = Limiter::new(
-        Quota::per_minute(
-            std::env::var("RATE_LIMIT")
-                .unwrap_or("20".to_string())
-                .parse()
-                .unwrap(),
-        )
-        .allow_burst(
-            std::env::var("RATE_LIMIT_BURST")
-                .unwrap_or("5".to_string())
-                .parse()
-                .unwrap(),
-        ),
-        &clock,
+    let rate_limiter: Arc<Limiter<i64>> = Limiter::new(config.rate_limit, &clock);
+
+    let storage = config
+        .dialogue_storage
+        .open::<State>()
+        .await
+        .expect("Failed to open dialogue storage");
+    let settings_storage = config
+        .settings_storage
+        .open()
+        .await
+        .expect("Failed to open settings storage");
+    let group_storage = config
+        .settings_storage
+        .open()
+        .await
+        .expect("Failed to open group settings storage");
+    let groups = Groups::new(group_storage, Limiter::new(config.group_rate_limit, &clock));
+    let catalogs = Catalogs::load();
+    let settings = Settings::new(settings_storage, config.export_defaults, catalogs.clone());
+
+    let transcoder = Transcoder::new(config.ffmpeg_path, config.ffmpeg_timeout);
+    let pool = ConversionPool::new(
+        config.conversion_workers,
+        config.conversion_workers_per_user,
+    );
+    let exporter = Exporter::new(
+        transcoder,
+        pool,
+        config.archive_split_size,
+        Jobs::new(config.pack_export_workers),
     );
 
-    Dispatcher::builder(
-        bot,
-        dptree::entry().branch(
-            Update::filter_message()
-                .enter_dialogue::<Message, InMemStorage<State>, State>()
-                .filter(|message: Message| message.chat.is_private()) // only handle private messages
-                .branch(
-                    dptree::case![State::Start]
-                        .filter_command::<BasicCommand>()
-                        .branch(dptree::case![BasicCommand::Start].endpoint(handle_start))
-                        .branch(dptree::case![BasicCommand::Help].endpoint(handle_help))
-                        .branch(
-                            dptree::case![BasicCommand::SingleExport]
-                                .endpoint(handle_single_export),
-                        )
-                        .branch(
-                            dptree::case![BasicCommand::PackExport].endpoint(handle_pack_export),
-                        ),
-                )
-                .branch(
-                    dptree::case![State::SingleExport]
-                        .filter(|message: Message| {
-                            message.text().map(|text| text != "/cancel").unwrap_or(true)
-                        })
-                        .endpoint(handle_export_sticker),
-                )
-                .branch(
-                    dptree::case![State::PackExport]
-                        .filter(|message: Message| {
-                            message.text().map(|text| text != "/cancel").unwrap_or(true)
-                        })
-                        .endpoint(handle_export_sticker),
-                )
-                .branch(
-                    dptree::entry()
-                        .filter(|message: Message| {
-                            message
-                                .text()
-                                .map(|text| text == "/cancel")
-                                .unwrap_or(false)
-                        })
-                        .endpoint(handle_cancel),
-                ),
-        ),
+    if let Err(e) = set_commands(&bot, &catalogs).await {
+        log::warn!("Failed to register commands: {:#}", e);
+    }
+
+    let mut dispatcher = Dispatcher::builder(
+        bot.clone(),
+        dptree::entry()
+            .branch(
+                Update::filter_message()
+                    .filter(|message: Message| {
+                        message.chat.is_group() || message.chat.is_supergroup()
+                    })
+                    .filter_command::<GroupCommand>()
+                    .branch(dptree::case![GroupCommand::Export(args)].endpoint(handle_group_export))
+                    .branch(
+                        dptree::case![GroupCommand::ExportPack(args)]
+                            .endpoint(handle_group_export_pack),
+                    )
+                    .branch(dptree::case![GroupCommand::Cancel].endpoint(handle_group_cancel))
+                    .branch(dptree::endpoint(handle_group_settings)),
+            )
+            .branch(
+                Update::filter_message()
+                    .enter_dialogue::<Message, StateStorage, State>()
+                    .filter(|message: Message| message.chat.is_private()) // only handle private messages
+                    .branch(
+                        // settings can be changed and deep links followed in any mode
+                        dptree::entry()
+                            .filter_command::<BasicCommand>()
+                            .branch(dptree::case![BasicCommand::Settings].endpoint(handle_settings))
+                            .branch(
+                                dptree::case![BasicCommand::Start(payload)]
+                                    .filter(|payload: String| !payload.trim().is_empty())
+                                    .endpoint(handle_start),
+                            ),
+                    )
+                    .branch(
+                        dptree::case![State::Start]
+                            .filter_command::<BasicCommand>()
+                            .branch(
+                                dptree::case![BasicCommand::Start(payload)].endpoint(handle_start),
+                            )
+                            .branch(dptree::case![BasicCommand::Help].endpoint(handle_help))
+                            .branch(
+                                dptree::case![BasicCommand::SingleExport(args)]
+                                    .endpoint(handle_single_export),
+                            )
+                            .branch(
+                                dptree::case![BasicCommand::PackExport(args)]
+                                    .endpoint(handle_pack_export),
+                            ),
+                    )
+                    .branch(
+                        dptree::case![State::SingleExport { format }]
+                            .filter(|message: Message| {
+                                message.text().map(|text| text != "/cancel").unwrap_or(true)
+                            })
+                            .endpoint(handle_export_sticker),
+                    )
+                    .branch(
+                        dptree::case![State::PackExport { format, archive }]
+                            .filter(|message: Message| {
+                                message.text().map(|text| text != "/cancel").unwrap_or(true)
+                            })
+                            .endpoint(handle_export_sticker),
+                    )
+                    .branch(
+                        dptree::entry()
+                            .filter(|message: Message| {
+                                message
+                                    .text()
+                                    .map(|text| text == "/cancel")
+                                    .unwrap_or(false)
+                            })
+                            .endpoint(handle_cancel),
+                    ),
+            )
+            .branch(
+                Update::filter_callback_query()
+                    .filter(|query: CallbackQuery| {
+                        query
+                            .data
+                            .as_deref()
+                            .is_some_and(|data| data.starts_with("settings:"))
+                    })
+                    .endpoint(handle_settings_callback),
+            )
+            .branch(
+                Update::filter_callback_query()
+                    .filter(|query: CallbackQuery| {
+                        query
+                            .data
+                            .as_deref()
+                            .is_some_and(|data| data.starts_with("cancel:"))
+                    })
+                    .endpoint(handle_cancel_callback),
+            )
+            .branch(
+                Update::filter_callback_query()
+                    .enter_dialogue::<CallbackQuery, StateStorage, State>()
+                    .endpoint(handle_format_callback),
+            )
+            .branch(Update::filter_inline_query().endpoint(handle_inline_query)),
     )
     .distribution_function(|_| None::<std::convert::Infallible>)
-    .dependencies(dptree::deps![InMemStorage::<State>::new(), rate_limiter])
-    .build()
-    .dispatch()
-    .await;
+    .error_handler(Arc::new(|error: anyhow::Error| async move {
+        metrics().record_error(&error);
+        log::error!("An error has occurred in the dispatcher: {:?}", error);
+    }))
+    .dependencies(dptree::deps![
+        storage,
+        rate_limiter,
+        groups,
+        exporter,
+        settings
+    ])
+    .build();
+
+    match config.webhook {
+        Some(webhook) => {
+            let listener = webhook::listen(bot, webhook)
+                .await
+                .expect("Failed to start the webhook listener");
+            readiness.set_ready(true);
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+                )
+                .await;
+        }
+        None => {
+            readiness.set_ready(true);
+            dispatcher.dispatch().await
+        }
+    }
 }
@@ -0,0 +1,199 @@
+use anyhow::Context;
+use serde_json::{json, Value};
+use teloxide::types::{Sticker, StickerFormat, StickerSet, StickerType};
+
+use crate::convert::OutputFormat;
+
+/// Machine-readable description of an exported sticker pack, stored as `manifest.json`.
+#[derive(Debug)]
+pub struct Manifest {
+    set: Value,
+    /// Stickers and failures with their position, they are added in completion order.
+    stickers: Vec<(usize, Value)>,
+    failures: Vec<(usize, Value)>,
+}
+
+impl Manifest {
+    pub fn new(sticker_set: &StickerSet, format: OutputFormat) -> Self {
+        Self {
+            set: json!({
+                "name": sticker_set.name,
+                "title": sticker_set.title,
+                "sticker_type": sticker_type_name(&sticker_set.kind),
+                "sticker_format": sticker_format_name(&sticker_set.format),
+                "sticker_count": sticker_set.stickers.len(),
+                "output_format": format.name(),
+            }),
+            stickers: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Record an exported sticker, `index` is its 0-based position in the set.
+    pub fn add_sticker(&mut self, index: usize, sticker: &Sticker, filename: &str) {
+        self.stickers.push((
+            index,
+            json!({
+                "position": index + 1,
+                "emoji": sticker.emoji,
+                "is_animated": sticker.is_animated(),
+                "is_video": sticker.is_video(),
+                "width": sticker.width,
+                "height": sticker.height,
+                "file_unique_id": sticker.file.unique_id,
+                "filename": filename,
+            }),
+        ));
+    }
+
+    /// Record a sticker that couldn't be exported, `index` is its 0-based position in the set.
+    pub fn add_failure(&mut self, index: usize, sticker: &Sticker, error: &str) {
+        self.failures.push((
+            index,
+            json!({
+                "position": index + 1,
+                "emoji": sticker.emoji,
+                "file_unique_id": sticker.file.unique_id,
+                "error": error,
+            }),
+        ));
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<Vec<u8>> {
+        let manifest = json!({
+            "generator": concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+            "sticker_set": self.set,
+            "stickers": in_pack_order(&self.stickers),
+            "failures": in_pack_order(&self.failures),
+        });
+
+        serde_json::to_vec_pretty(&manifest).context("Failed to serialize manifest")
+    }
+}
+
+fn in_pack_order(entries: &[(usize, Value)]) -> Vec<&Value> {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(index, _)| *index);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn sticker_type_name(kind: &StickerType) -> &'static str {
+    match kind {
+        StickerType::Regular => "regular",
+        StickerType::Mask => "mask",
+        StickerType::CustomEmoji => "custom_emoji",
+    }
+}
+
+fn sticker_format_name(format: &StickerFormat) -> &'static str {
+    match format {
+        StickerFormat::Raster => "static",
+        StickerFormat::Animated => "animated",
+        StickerFormat::Video => "video",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sticker(unique_id: &str, emoji: &str) -> Value {
+        json!({
+            "file_id": format!("CAACAgIAAx{}", unique_id),
+            "file_unique_id": unique_id,
+            "width": 512,
+            "height": 512,
+            "is_animated": false,
+            "is_video": true,
+            "type": "regular",
+            "emoji": emoji,
+            "set_name": "cats",
+        })
+    }
+
+    fn sticker_set() -> StickerSet {
+        serde_json::from_value(json!({
+            "name": "cats",
+            "title": "Cats",
+            "sticker_type": "regular",
+            "is_animated": false,
+            "is_video": true,
+            "stickers": [sticker("AgADaaa", "😺"), sticker("AgADbbb", "😿"), sticker("AgADccc", "🙀")],
+        }))
+        .unwrap()
+    }
+
+    fn to_value(manifest: &Manifest) -> Value {
+        serde_json::from_slice(&manifest.to_json().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn describes_the_sticker_set() {
+        let manifest = to_value(&Manifest::new(&sticker_set(), OutputFormat::Gif));
+
+        assert_eq!(
+            manifest["sticker_set"],
+            json!({
+                "name": "cats",
+                "title": "Cats",
+                "sticker_type": "regular",
+                "sticker_format": "video",
+                "sticker_count": 3,
+                "output_format": "gif",
+            })
+        );
+        assert!(manifest["generator"]
+            .as_str()
+            .unwrap()
+            .starts_with(env!("CARGO_PKG_NAME")));
+        assert_eq!(manifest["stickers"], json!([]));
+        assert_eq!(manifest["failures"], json!([]));
+    }
+
+    #[test]
+    fn lists_stickers_and_failures_in_pack_order() {
+        let sticker_set = sticker_set();
+        let stickers = &sticker_set.stickers;
+        let mut manifest = Manifest::new(&sticker_set, OutputFormat::Gif);
+        // stickers are added as their conversions complete
+        manifest.add_sticker(2, &stickers[2], "3_🙀.gif");
+        manifest.add_failure(1, &stickers[1], "Failed to download sticker");
+        manifest.add_sticker(0, &stickers[0], "1_😺.gif");
+        let manifest = to_value(&manifest);
+
+        assert_eq!(
+            manifest["stickers"],
+            json!([
+                {
+                    "position": 1,
+                    "emoji": "😺",
+                    "is_animated": false,
+                    "is_video": true,
+                    "width": 512,
+                    "height": 512,
+                    "file_unique_id": "AgADaaa",
+                    "filename": "1_😺.gif",
+                },
+                {
+                    "position": 3,
+                    "emoji": "🙀",
+                    "is_animated": false,
+                    "is_video": true,
+                    "width": 512,
+                    "height": 512,
+                    "file_unique_id": "AgADccc",
+                    "filename": "3_🙀.gif",
+                },
+            ])
+        );
+        assert_eq!(
+            manifest["failures"],
+            json!([{
+                "position": 2,
+                "emoji": "😿",
+                "file_unique_id": "AgADbbb",
+                "error": "Failed to download sticker",
+            }])
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::Sticker;
+
+/// Characters that are not allowed in file names on common file systems.
+const RESERVED_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Separators dropped together with an empty placeholder, and trimmed from the ends of a name.
+const SEPARATORS: [char; 3] = ['_', '-', ' '];
+
+/// Maximum length of a file name stem, in characters.
+const MAX_STEM_LEN: usize = 96;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Index,
+    Emoji,
+    UniqueId,
+    SetName,
+}
+
+/// Template for the names of the files in a pack archive.
+///
+/// Supported placeholders are `{index}` (the 1-based position in the sticker set,
+/// zero-padded to the size of the set), `{emoji}`, `{unique_id}` and `{set_name}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FilenameTemplate(Vec<Part>);
+
+impl FilenameTemplate {
+    /// Render the file name stem of the sticker at `index` (0-based) of a set of `total` stickers.
+    pub fn render(&self, index: usize, total: usize, sticker: &Sticker) -> String {
+        let width = total.to_string().len();
+        let mut name = String::new();
+
+        for part in &self.0 {
+            let value = match part {
+                Part::Literal(literal) => {
+                    name.push_str(literal);
+                    continue;
+                }
+                Part::Index => format!("{:0width$}", index + 1, width = width),
+                Part::Emoji => sticker.emoji.clone().unwrap_or_default(),
+                Part::UniqueId => sticker.file.unique_id.clone(),
+                Part::SetName => sticker.set_name.clone().unwrap_or_default(),
+            };
+
+            if value.is_empty() {
+                // drop the separator leading up to the missing part
+                if name.ends_with(SEPARATORS) {
+                    name.pop();
+                }
+            } else {
+                name.push_str(&value);
+            }
+        }
+
+        sanitize(&name)
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in filename template"))?;
+
+            parts.push(match &rest[start + 1..end] {
+                "index" => Part::Index,
+                "emoji" => Part::Emoji,
+                "unique_id" => Part::UniqueId,
+                "set_name" => Part::SetName,
+                placeholder => {
+                    return Err(anyhow::anyhow!(
+                        "Unknown placeholder `{{{}}}` in filename template",
+                        placeholder
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        if !parts.iter().any(|part| !matches!(part, Part::Literal(_))) {
+            return Err(anyhow::anyhow!(
+                "Filename template must contain at least one placeholder"
+            ));
+        }
+
+        Ok(Self(parts))
+    }
+}
+
+impl Display for FilenameTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for part in &self.0 {
+            f.write_str(match part {
+                Part::Literal(literal) => literal,
+                Part::Index => "{index}",
+                Part::Emoji => "{emoji}",
+                Part::UniqueId => "{unique_id}",
+                Part::SetName => "{set_name}",
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for FilenameTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FilenameTemplate> for String {
+    fn from(template: FilenameTemplate) -> Self {
+        template.to_string()
+    }
+}
+
+/// Make a file name stem safe for archives and file systems, keeping any Unicode (emoji included).
+pub fn sanitize(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || RESERVED_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_STEM_LEN)
+        .collect::<String>();
+
+    // leading dots would hide the file, trailing dots and spaces are stripped by Windows
+    let name = name
+        .trim_start_matches('.')
+        .trim_end_matches('.')
+        .trim_matches(SEPARATORS);
+
+    if name.is_empty() {
+        "sticker".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Tracks the names used in an archive and resolves collisions with a numeric suffix.
+#[derive(Debug, Default)]
+pub struct ArchiveNames(HashSet<String>);
+
+impl ArchiveNames {
+    /// Reserve a unique file name built from `stem` and `extension`.
+    pub fn reserve(&mut self, stem: &str, extension: &str) -> String {
+        let mut name = format!("{}.{}", stem, extension);
+        let mut counter = 2;
+
+        // names are compared case-insensitively, as they are on most desktop file systems
+        while !self.0.insert(name.to_lowercase()) {
+            name = format!("{}_{}.{}", stem, counter, extension);
+            counter += 1;
+        }
+
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sticker(emoji: Option<&str>) -> Sticker {
+        serde_json::from_value(serde_json::json!({
+            "file_id": "CAACAgIAAxkBAAEB",
+            "file_unique_id": "AgADxyz",
+            "width": 512,
+            "height": 512,
+            "is_animated": false,
+            "is_video": false,
+            "type": "regular",
+            "emoji": emoji,
+            "set_name": "cats",
+        }))
+        .unwrap()
+    }
+
+    fn render(template: &str, index: usize, total: usize, sticker: &Sticker) -> String {
+        template
+            .parse::<FilenameTemplate>()
+            .unwrap()
+            .render(index, total, sticker)
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let sticker = sticker(Some("😺"));
+        assert_eq!(render("{index}_{emoji}", 4, 120, &sticker), "005_😺");
+        assert_eq!(render("{set_name}-{index}", 0, 9, &sticker), "cats-1");
+        assert_eq!(render("{unique_id}", 0, 1, &sticker), "AgADxyz");
+    }
+
+    #[test]
+    fn drops_separator_of_missing_parts() {
+        let sticker = sticker(None);
+        assert_eq!(render("{index}_{emoji}", 0, 10, &sticker), "01");
+        assert_eq!(render("{emoji} {index}", 0, 10, &sticker), "01");
+    }
+
+    #[test]
+    fn sanitizes_rendered_names() {
+        let sticker = sticker(Some("a/b:c"));
+        assert_eq!(render("{emoji}", 0, 1, &sticker), "a_b_c");
+        assert_eq!(sanitize("..hidden."), "hidden");
+        assert_eq!(sanitize("___"), "sticker");
+        assert_eq!(sanitize(&"x".repeat(200)).len(), MAX_STEM_LEN);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!("{index".parse::<FilenameTemplate>().is_err());
+        assert!("{size}".parse::<FilenameTemplate>().is_err());
+        assert!("sticker".parse::<FilenameTemplate>().is_err());
+        assert_eq!(
+            "{set_name}/{index}"
+                .parse::<FilenameTemplate>()
+                .unwrap()
+                .to_string(),
+            "{set_name}/{index}"
+        );
+    }
+
+    #[test]
+    fn suffixes_colliding_names() {
+        let mut names = ArchiveNames::default();
+        assert_eq!(names.reserve("manifest", "json"), "manifest.json");
+        assert_eq!(names.reserve("01_😺", "png"), "01_😺.png");
+        assert_eq!(names.reserve("01_😺", "png"), "01_😺_2.png");
+        assert_eq!(names.reserve("01_😺", "png"), "01_😺_3.png");
+        // names only differing in case collide as well
+        assert_eq!(names.reserve("Manifest", "json"), "Manifest_2.json");
+        // the same stem with another extension is a different name
+        assert_eq!(names.reserve("01_😺", "webp"), "01_😺.webp");
+    }
+}
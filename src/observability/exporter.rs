@@ -0,0 +1,173 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use opentelemetry::global;
+use opentelemetry_otlp::{
+    ExportConfig, HttpExporterBuilder, TonicExporterBuilder, WithExportConfig,
+};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+/// Where telemetry is exported, selected with `otel_exporter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Exporter {
+    /// Nothing is exported, metrics are still served on `/metrics`.
+    None,
+    Stdout,
+    /// JSON files in a directory, one per signal.
+    File(PathBuf),
+    /// OTLP over gRPC to the given endpoint.
+    OtlpGrpc(String),
+    /// OTLP over HTTP, posting to `<endpoint>/v1/<signal>`.
+    OtlpHttp(String),
+}
+
+impl Exporter {
+    /// Where the stdout and file exporters write a signal (`traces`, `metrics` or `logs`).
+    pub fn writer(&self, signal: &str) -> anyhow::Result<Box<dyn Write + Send + Sync>> {
+        match self {
+            Exporter::File(directory) => {
+                fs::create_dir_all(directory)
+                    .with_context(|| format!("Failed to create {}", directory.display()))?;
+                let path = directory.join(format!("{}.json", signal));
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+
+                Ok(Box::new(file))
+            }
+            _ => Ok(Box::new(std::io::stdout())),
+        }
+    }
+
+    /// Build the OTLP exporter of a signal.
+    ///
+    /// Headers, timeouts and per-signal endpoints are read by the exporter from the standard
+    /// `OTEL_EXPORTER_OTLP_*` variables.
+    pub fn otlp<B>(&self, signal: &str) -> anyhow::Result<B>
+    where
+        B: From<TonicExporterBuilder> + From<HttpExporterBuilder>,
+    {
+        match self {
+            Exporter::OtlpHttp(endpoint) => {
+                // endpoints configured when only traces were exported include the path
+                let base = endpoint
+                    .trim_end_matches('/')
+                    .trim_end_matches("/v1/traces");
+
+                Ok(HttpExporterBuilder::default()
+                    .with_export_config(ExportConfig {
+                        endpoint: format!("{}/v1/{}", base, signal),
+                        ..Default::default()
+                    })
+                    .into())
+            }
+            Exporter::OtlpGrpc(endpoint) => {
+                let mut builder = TonicExporterBuilder::default();
+                // the standard variable takes precedence in the exporter
+                let effective_endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                    .unwrap_or_else(|_| endpoint.clone());
+                if let Some(tls) = tonic_tls(&effective_endpoint)? {
+                    builder = builder.with_tls_config(tls);
+                }
+
+                Ok(builder
+                    .with_export_config(ExportConfig {
+                        endpoint: endpoint.clone(),
+                        ..Default::default()
+                    })
+                    .into())
+            }
+            _ => Err(anyhow::anyhow!("{:?} is not an OTLP exporter", self)),
+        }
+    }
+}
+
+/// TLS settings of the gRPC exporter, from the standard `OTEL_EXPORTER_OTLP_CERTIFICATE`,
+/// `OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE` and `OTEL_EXPORTER_OTLP_CLIENT_KEY` variables.
+///
+/// `https` endpoints without them are verified against the system's root certificates.
+fn tonic_tls(endpoint: &str) -> anyhow::Result<Option<ClientTlsConfig>> {
+    let read = |key: &str| -> anyhow::Result<Option<Vec<u8>>> {
+        match std::env::var(key) {
+            Ok(path) => {
+                Ok(Some(fs::read(&path).with_context(|| {
+                    format!("Failed to read `{}` {}", key, path)
+                })?))
+            }
+            Err(_) => Ok(None),
+        }
+    };
+
+    let ca = read("OTEL_EXPORTER_OTLP_CERTIFICATE")?;
+    let certificate = read("OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE")?;
+    let key = read("OTEL_EXPORTER_OTLP_CLIENT_KEY")?;
+    if !endpoint.starts_with("https://") && ca.is_none() && certificate.is_none() {
+        return Ok(None);
+    }
+
+    let mut tls = ClientTlsConfig::new();
+    if let Some(ca) = ca {
+        tls = tls.ca_certificate(Certificate::from_pem(ca));
+    }
+    match (certificate, key) {
+        (Some(certificate), Some(key)) => tls = tls.identity(Identity::from_pem(certificate, key)),
+        (None, None) => {}
+        _ => {
+            return Err(anyhow::anyhow!(
+                "`OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE` and `OTEL_EXPORTER_OTLP_CLIENT_KEY` must be set together"
+            ))
+        }
+    }
+
+    Ok(Some(tls))
+}
+
+/// Keep going without exporting a signal whose exporter can't be set up.
+pub fn or_disable<T>(signal: &str, result: anyhow::Result<T>) -> Option<T> {
+    match result {
+        Ok(exporter) => Some(exporter),
+        Err(e) => {
+            // logging may not be set up yet
+            eprintln!(
+                "Failed to set up the {} exporter, {} are not exported: {:#}",
+                signal, signal, e
+            );
+            None
+        }
+    }
+}
+
+/// Report export errors at most once a minute, so an unreachable collector doesn't flood the logs.
+pub fn init_error_handler() {
+    static LAST_REPORT: Mutex<Option<(Instant, u64)>> = Mutex::new(None);
+
+    let result = global::set_error_handler(|error| {
+        let mut last_report = LAST_REPORT.lock().unwrap();
+        match last_report.as_mut() {
+            Some((reported_at, suppressed)) if reported_at.elapsed() < Duration::from_secs(60) => {
+                *suppressed += 1;
+            }
+            _ => {
+                let suppressed = last_report.map_or(0, |(_, suppressed)| suppressed);
+                // the target keeps these out of the exported logs
+                log::warn!(
+                    target: "opentelemetry",
+                    "Failed to export telemetry: {} ({} similar errors suppressed)",
+                    error,
+                    suppressed
+                );
+                *last_report = Some((Instant::now(), 0));
+            }
+        }
+    });
+
+    if let Err(e) = result {
+        eprintln!("Failed to set the OpenTelemetry error handler: {}", e);
+    }
+}
@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+
+use opentelemetry::ContextGuard;
+use opentelemetry_otlp::LogExporterBuilder;
+use opentelemetry_sdk::logs::{Config, LoggerProvider};
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::Tracer;
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_opentelemetry::{OtelData, PreSampledTracer};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::observability::exporter::{or_disable, Exporter};
+use crate::observability::resource::init_resource;
+
+/// Build the provider of the logs pipeline, which exports nothing if the exporter can't be set up.
+pub fn init_logger_provider(exporter: &Exporter) -> LoggerProvider {
+    let builder =
+        LoggerProvider::builder().with_config(Config::default().with_resource(init_resource()));
+
+    match exporter {
+        Exporter::None => builder.build(),
+        Exporter::Stdout | Exporter::File(_) => match or_disable("logs", exporter.writer("logs")) {
+            Some(writer) => builder
+                .with_batch_exporter(
+                    opentelemetry_stdout::LogExporter::builder()
+                        .with_writer(writer)
+                        .build(),
+                    Tokio,
+                )
+                .build(),
+            None => builder.build(),
+        },
+        Exporter::OtlpGrpc(_) | Exporter::OtlpHttp(_) => {
+            let otlp = exporter
+                .otlp::<LogExporterBuilder>("logs")
+                .and_then(|otlp| Ok(otlp.build_log_exporter()?));
+            match or_disable("logs", otlp) {
+                Some(otlp) => builder.with_batch_exporter(otlp, Tokio).build(),
+                None => builder.build(),
+            }
+        }
+    }
+}
+
+/// Whether events of a target come from the exporters themselves, which would loop if exported.
+pub fn is_exporter_target(target: &str) -> bool {
+    ["opentelemetry", "tonic", "h2", "hyper", "reqwest"]
+        .iter()
+        .any(|prefix| target.starts_with(prefix))
+}
+
+thread_local! {
+    static ENTERED: RefCell<Vec<(Id, Option<ContextGuard>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Makes the OpenTelemetry context of the entered `tracing` span current,
+/// so log records emitted inside it carry its trace and span ids.
+///
+/// Must be added after the `tracing_opentelemetry` layer.
+pub struct TraceContextLayer {
+    tracer: Tracer,
+}
+
+impl TraceContextLayer {
+    pub fn new(tracer: Tracer) -> Self {
+        Self { tracer }
+    }
+}
+
+impl<S> Layer<S> for TraceContextLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let guard = ctx.span(id).and_then(|span| {
+            span.extensions_mut()
+                .get_mut::<OtelData>()
+                .map(|data| self.tracer.sampled_context(data).attach())
+        });
+
+        ENTERED.with(|entered| entered.borrow_mut().push((id.clone(), guard)));
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: Context<'_, S>) {
+        // spans aren't always exited in reverse order, e.g. by futures polled in turn
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|(entered, _)| entered == id) {
+                entered.remove(index);
+            }
+        });
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use opentelemetry::metrics::{Counter, Histogram, UpDownCounter};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::MetricsExporterBuilder;
+use opentelemetry_sdk::metrics::reader::{DefaultAggregationSelector, DefaultTemporalitySelector};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::runtime::Tokio;
+use prometheus::Registry;
+use teloxide::types::StickerFormat;
+use teloxide::{DownloadError, RequestError};
+
+use crate::convert::OutputFormat;
+use crate::observability::exporter::{or_disable, Exporter};
+use crate::observability::resource::init_resource;
+
+/// Install the global meter provider, which exports with `exporter`
+/// and to the registry it returns, served on `/metrics`.
+///
+/// Must run before [`metrics`] is first called, instruments created earlier record nothing.
+pub fn init_metrics(exporter: &Exporter) -> Registry {
+    let registry = Registry::new();
+    let prometheus = opentelemetry_prometheus::exporter()
+        .with_registry(registry.clone())
+        .build()
+        .expect("Failed to build the Prometheus exporter");
+
+    let mut builder = SdkMeterProvider::builder()
+        .with_reader(prometheus)
+        .with_resource(init_resource());
+    match exporter {
+        Exporter::None => {}
+        Exporter::Stdout | Exporter::File(_) => {
+            if let Some(writer) = or_disable("metrics", exporter.writer("metrics")) {
+                let stdout = opentelemetry_stdout::MetricsExporter::builder()
+                    .with_writer(writer)
+                    .build();
+                builder = builder.with_reader(PeriodicReader::builder(stdout, Tokio).build());
+            }
+        }
+        Exporter::OtlpGrpc(_) | Exporter::OtlpHttp(_) => {
+            let otlp = exporter
+                .otlp::<MetricsExporterBuilder>("metrics")
+                .and_then(|otlp| {
+                    Ok(otlp.build_metrics_exporter(
+                        Box::new(DefaultTemporalitySelector::new()),
+                        Box::new(DefaultAggregationSelector::new()),
+                    )?)
+                });
+            if let Some(otlp) = or_disable("metrics", otlp) {
+                builder = builder.with_reader(PeriodicReader::builder(otlp, Tokio).build());
+            }
+        }
+    }
+    global::set_meter_provider(builder.build());
+
+    registry
+}
+
+/// Instruments of the bot.
+pub struct Metrics {
+    exports: Counter<u64>,
+    conversion_duration: Histogram<f64>,
+    queue_depth: UpDownCounter<i64>,
+    rate_limited: Counter<u64>,
+    telegram_errors: Counter<u64>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The bot's instruments, created on first use.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        let meter = global::meter("sticker-export-bot");
+
+        Metrics {
+            exports: meter
+                .u64_counter("sticker_exports")
+                .with_description("Finished exports by type, output format and outcome")
+                .init(),
+            conversion_duration: meter
+                .f64_histogram("sticker_conversion_duration")
+                .with_description("Time spent converting a sticker")
+                .with_unit("ms")
+                .init(),
+            queue_depth: meter
+                .i64_up_down_counter("conversion_queue_depth")
+                .with_description("Sticker exports waiting for a conversion slot")
+                .init(),
+            rate_limited: meter
+                .u64_counter("rate_limit_rejections")
+                .with_description("Requests rejected by the rate limits, by chat type")
+                .init(),
+            telegram_errors: meter
+                .u64_counter("telegram_api_errors")
+                .with_description("Failed Telegram API requests and file downloads, by kind")
+                .init(),
+        }
+    })
+}
+
+impl Metrics {
+    /// Count a finished export, `kind` is `sticker` or `pack`.
+    pub fn record_export(&self, kind: &'static str, format: OutputFormat, outcome: &'static str) {
+        self.exports.add(
+            1,
+            &[
+                KeyValue::new("type", kind),
+                KeyValue::new("format", format.name()),
+                KeyValue::new("outcome", outcome),
+            ],
+        );
+    }
+
+    /// Record how long a successful conversion took.
+    pub fn record_conversion(
+        &self,
+        sticker_format: &StickerFormat,
+        format: OutputFormat,
+        duration: Duration,
+    ) {
+        let sticker = match sticker_format {
+            StickerFormat::Raster => "static",
+            StickerFormat::Animated => "animated",
+            StickerFormat::Video => "video",
+        };
+
+        self.conversion_duration.record(
+            duration.as_secs_f64() * 1000.0,
+            &[
+                KeyValue::new("sticker", sticker),
+                KeyValue::new("format", format.name()),
+            ],
+        );
+    }
+
+    /// Count a job in the conversion queue until the returned guard is dropped.
+    pub fn queued(&'static self) -> QueuedGuard {
+        self.queue_depth.add(1, &[]);
+        QueuedGuard(&self.queue_depth)
+    }
+
+    /// Count a request rejected by a rate limit, `chat` is `private` or `group`.
+    pub fn record_rate_limited(&self, chat: &'static str) {
+        self.rate_limited.add(1, &[KeyValue::new("chat", chat)]);
+    }
+
+    /// Count the Telegram API or download error in the chain of `error`, if any.
+    pub fn record_error(&self, error: &anyhow::Error) {
+        let kind = error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<RequestError>() {
+                Some(match e {
+                    RequestError::Api(_) => "api",
+                    RequestError::MigrateToChatId(_) => "migrate_to_chat_id",
+                    RequestError::RetryAfter(_) => "retry_after",
+                    RequestError::Network(_) => "network",
+                    RequestError::InvalidJson { .. } => "invalid_json",
+                    RequestError::Io(_) => "io",
+                })
+            } else {
+                cause.downcast_ref::<DownloadError>().map(|e| match e {
+                    DownloadError::Network(_) => "download_network",
+                    DownloadError::Io(_) => "download_io",
+                })
+            }
+        });
+
+        if let Some(kind) = kind {
+            self.telegram_errors.add(1, &[KeyValue::new("kind", kind)]);
+        }
+    }
+}
+
+/// Keeps a job counted in the conversion queue depth.
+pub struct QueuedGuard(&'static UpDownCounter<i64>);
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.0.add(-1, &[]);
+    }
+}
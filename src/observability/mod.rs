@@ -1,2 +1,6 @@
+pub(crate) mod exporter;
+mod logs;
+pub(crate) mod metrics;
 mod resource;
+pub(crate) mod server;
 pub(crate) mod tracing;
@@ -0,0 +1,83 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use prometheus::{Encoder, Registry, TextEncoder};
+
+/// Whether the bot is ready to handle updates, reported on `/readyz`.
+#[derive(Debug, Default)]
+pub struct Readiness(AtomicBool);
+
+impl Readiness {
+    pub fn set_ready(&self, ready: bool) {
+        self.0.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    registry: Registry,
+    readiness: Arc<Readiness>,
+}
+
+/// Serve `/healthz`, `/readyz` and `/metrics` in the background.
+pub fn serve(
+    address: SocketAddr,
+    registry: Registry,
+    readiness: Arc<Readiness>,
+) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(ready))
+        .route("/metrics", get(metrics))
+        .with_state(ServerState {
+            registry,
+            readiness,
+        });
+
+    let server = axum::Server::try_bind(&address)
+        .with_context(|| format!("Failed to bind {}", address))?
+        .serve(router.into_make_service());
+
+    log::info!("Serving health checks and metrics on {}", address);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("Monitoring server error: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn ready(State(state): State<ServerState>) -> impl IntoResponse {
+    if state.readiness.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "starting")
+    }
+}
+
+async fn metrics(State(state): State<ServerState>) -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&state.registry.gather(), &mut buffer) {
+        log::error!("Failed to encode metrics: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Vec::new()).into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
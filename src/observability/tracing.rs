@@ -1,58 +1,80 @@
 use opentelemetry::global;
-use opentelemetry_otlp::{
-    ExportConfig, HttpExporterBuilder, SpanExporterBuilder, TonicExporterBuilder, WithExportConfig,
-};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::SpanExporterBuilder;
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use opentelemetry_sdk::runtime::Tokio;
-use opentelemetry_sdk::trace::Sampler;
-use tracing_subscriber::{EnvFilter, Registry};
+use opentelemetry_sdk::trace::{Config, Sampler, TracerProvider};
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
 
+use crate::observability::exporter::{init_error_handler, or_disable, Exporter};
+use crate::observability::logs::{init_logger_provider, is_exporter_target, TraceContextLayer};
 use crate::observability::resource::init_resource;
 
-pub fn init_tracer() {
-    let export_config = ExportConfig {
-        endpoint: std::env::var("OTEL_EXPORTER_ENDPOINT")
-            .unwrap_or_else(|_| "http://localhost:4317".to_string()),
-        ..Default::default()
-    };
+/// Install the traces and logs pipelines, and route `log` records through `tracing`.
+pub fn init_tracer(exporter: &Exporter, sample_rate: f64) {
+    init_error_handler();
+    global::set_text_map_propagator(TraceContextPropagator::new());
 
-    let exporter = match std::env::var("OTEL_EXPORTER")
-        .unwrap_or_else(|_| "otlp_grpc".to_string())
-        .as_str()
-    {
-        "otlp_http" => SpanExporterBuilder::Http(
-            HttpExporterBuilder::default().with_export_config(export_config),
-        ),
-        "otlp_grpc" => SpanExporterBuilder::Tonic(
-            TonicExporterBuilder::default().with_export_config(export_config),
-        ),
-        _ => {
-            panic!("`OTEL_EXPORTER` not supported");
+    let builder = TracerProvider::builder().with_config(trace_config(sample_rate));
+    let provider = match exporter {
+        Exporter::None => builder.build(),
+        Exporter::Stdout | Exporter::File(_) => {
+            match or_disable("traces", exporter.writer("traces")) {
+                Some(writer) => builder
+                    .with_batch_exporter(
+                        opentelemetry_stdout::SpanExporter::builder()
+                            .with_writer(writer)
+                            .build(),
+                        Tokio,
+                    )
+                    .build(),
+                None => builder.build(),
+            }
+        }
+        Exporter::OtlpGrpc(_) | Exporter::OtlpHttp(_) => {
+            let otlp = exporter
+                .otlp::<SpanExporterBuilder>("traces")
+                .and_then(|otlp| Ok(otlp.build_span_exporter()?));
+            match or_disable("traces", otlp) {
+                Some(otlp) => builder.with_batch_exporter(otlp, Tokio).build(),
+                None => builder.build(),
+            }
         }
     };
+    let tracer = provider.tracer("sticker-export-bot");
+    global::set_tracer_provider(provider);
+    let logger_provider = init_logger_provider(exporter);
 
-    global::set_text_map_propagator(TraceContextPropagator::new());
-
-    let tracer = opentelemetry_otlp::new_pipeline()
-        .tracing()
-        .with_exporter(exporter)
-        .with_trace_config(
-            opentelemetry_sdk::trace::config()
-                .with_sampler(Sampler::TraceIdRatioBased(
-                    std::env::var("OTEL_SAMPLE_RATE")
-                        .unwrap_or_else(|_| "1".to_string())
-                        .parse()
-                        .unwrap(),
-                ))
-                .with_resource(init_resource()),
-        )
-        .install_batch(Tokio)
-        .expect("Failed to install `opentelemetry` tracer.");
-
-    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer.clone());
+    let logs = OpenTelemetryTracingBridge::new(&logger_provider)
+        .with_filter(filter_fn(|metadata| !is_exporter_target(metadata.target())));
     let env_filter = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("INFO"));
-    let subscriber = Registry::default().with(telemetry).with(env_filter);
-    tracing::subscriber::set_global_default(subscriber)
+    let subscriber = Registry::default()
+        .with(telemetry)
+        .with(TraceContextLayer::new(tracer))
+        .with(logs)
+        .with(tracing_subscriber::fmt::layer())
+        .with(env_filter);
+    // also installs the `log` bridge
+    subscriber
+        .try_init()
         .expect("Failed to install `tracing` subscriber.");
 }
+
+/// Sample with the standard `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG` variables if set,
+/// otherwise follow the parent's decision and sample new traces at `sample_rate`.
+fn trace_config(sample_rate: f64) -> Config {
+    // the default config reads the standard variables
+    let config = Config::default().with_resource(init_resource());
+    if std::env::var("OTEL_TRACES_SAMPLER").is_ok() {
+        return config;
+    }
+
+    config.with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        sample_rate,
+    ))))
+}
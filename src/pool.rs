@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::observability::metrics::metrics;
+
+/// Shared pool bounding the number of sticker exports (download and conversion) running at once.
+///
+/// Besides the global limit every user can only occupy `per_user` slots, so a large pack
+/// export can't starve everyone else. Waiters are served in FIFO order.
+#[derive(Debug)]
+pub struct ConversionPool {
+    global: Arc<Semaphore>,
+    per_user: usize,
+    users: Mutex<HashMap<i64, Arc<Semaphore>>>,
+}
+
+impl ConversionPool {
+    pub fn new(size: usize, per_user: usize) -> Arc<Self> {
+        Arc::new(Self {
+            global: Arc::new(Semaphore::new(size.max(1))),
+            per_user: per_user.clamp(1, size.max(1)),
+            users: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Run a job for the given user once a slot is available.
+    pub async fn run<F: Future>(&self, user: i64, job: F) -> F::Output {
+        let _permits = self.acquire(user).await;
+        job.await
+    }
+
+    async fn acquire(&self, user: i64) -> (OwnedSemaphorePermit, OwnedSemaphorePermit) {
+        let _queued = metrics().queued();
+        let user_semaphore = {
+            let mut users = self.users.lock().unwrap();
+            // drop the semaphores of users without running or waiting jobs
+            users.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+            users
+                .entry(user)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.per_user)))
+                .clone()
+        };
+
+        // the semaphores are never closed
+        let user_permit = user_semaphore.acquire_owned().await.unwrap();
+        let global_permit = self.global.clone().acquire_owned().await.unwrap();
+
+        (user_permit, global_permit)
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use teloxide::dispatching::dialogue::ErasedStorage;
+use teloxide::types::{User, UserId};
+
+use crate::archive::ArchiveFormat;
+use crate::convert::{AnimationOptions, OutputFormat};
+use crate::export::ExportOptions;
+use crate::i18n::{self, Catalogs, Locale};
+use crate::naming::FilenameTemplate;
+use crate::storage::{Namespace, Versioned};
+
+/// Animation widths offered in `/settings`, in pixels.
+pub const WIDTHS: [u32; 4] = [128, 256, 320, 512];
+
+/// Animation frame rates offered in `/settings`.
+pub const FRAME_RATES: [u32; 5] = [10, 15, 24, 30, 50];
+
+/// Filename templates offered in `/settings`.
+pub const FILENAME_TEMPLATES: [&str; 4] = [
+    "{index}_{emoji}",
+    "{index}",
+    "{unique_id}",
+    "{set_name}_{index}",
+];
+
+/// Export preferences of a user, edited with `/settings`.
+///
+/// Unset formats and filename template fall back to the bot's defaults,
+/// an unset language to the one of the user's Telegram client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    pub format: Option<OutputFormat>,
+    pub archive: Option<ArchiveFormat>,
+    pub filename_template: Option<FilenameTemplate>,
+    /// Width of animations, in pixels.
+    pub width: u32,
+    /// Frame rate of animations.
+    pub fps: u32,
+    /// Whether pack archives include `manifest.json`.
+    pub manifest: bool,
+    pub language: Option<String>,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        let animation = AnimationOptions::default();
+
+        Self {
+            format: None,
+            archive: None,
+            filename_template: None,
+            width: animation.width,
+            fps: animation.fps,
+            manifest: true,
+            language: None,
+        }
+    }
+}
+
+impl Versioned for UserSettings {
+    const VERSION: u64 = 0;
+
+    const MIGRATIONS: &'static [fn(Value) -> Value] = &[];
+}
+
+impl UserSettings {
+    /// Resolve the settings against the bot's defaults.
+    pub fn resolve(&self, defaults: &ExportOptions) -> ExportOptions {
+        ExportOptions {
+            format: self.format.unwrap_or(defaults.format),
+            archive: self.archive.unwrap_or(defaults.archive),
+            filename_template: self
+                .filename_template
+                .clone()
+                .unwrap_or_else(|| defaults.filename_template.clone()),
+            animation: AnimationOptions {
+                fps: self.fps,
+                width: self.width,
+            },
+            manifest: self.manifest,
+        }
+    }
+
+    /// The callback value of a setting, `default` for unset formats, filename template and language.
+    pub fn value(&self, key: &str) -> Option<String> {
+        Some(match key {
+            "format" => self
+                .format
+                .map_or("default", |format| format.name())
+                .to_string(),
+            "archive" => self
+                .archive
+                .map_or("default", |archive| archive.name())
+                .to_string(),
+            "template" => self
+                .filename_template
+                .as_ref()
+                .map_or("default".to_string(), ToString::to_string),
+            "width" => self.width.to_string(),
+            "fps" => self.fps.to_string(),
+            "manifest" => if self.manifest { "on" } else { "off" }.to_string(),
+            "language" => self.language.as_deref().unwrap_or("default").to_string(),
+            _ => return None,
+        })
+    }
+
+    /// Change a setting from its callback value, limited to the choices offered in `/settings`.
+    pub fn update(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        match (key, value) {
+            ("format", "default") => self.format = None,
+            ("format", value) => self.format = Some(value.parse()?),
+            ("archive", "default") => self.archive = None,
+            ("archive", value) => self.archive = Some(value.parse()?),
+            ("template", "default") => self.filename_template = None,
+            ("template", value) if FILENAME_TEMPLATES.contains(&value) => {
+                self.filename_template = Some(value.parse()?)
+            }
+            ("width", value) => self.width = parse_choice(&WIDTHS, value)?,
+            ("fps", value) => self.fps = parse_choice(&FRAME_RATES, value)?,
+            ("manifest", "on" | "off") => self.manifest = value == "on",
+            ("language", "default") => self.language = None,
+            ("language", value) if i18n::languages().any(|language| language == value) => {
+                self.language = Some(value.to_string())
+            }
+            _ => return Err(anyhow::anyhow!("Unknown setting: {}={}", key, value)),
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a number that has to be one of the offered choices.
+fn parse_choice(choices: &[u32], value: &str) -> anyhow::Result<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|value| choices.contains(value))
+        .ok_or_else(|| anyhow::anyhow!("Unsupported value: {}", value))
+}
+
+/// The stored settings of all users, along with the bot's defaults and message catalogs.
+pub struct Settings {
+    storage: Arc<ErasedStorage<UserSettings>>,
+    pub defaults: ExportOptions,
+    catalogs: Arc<Catalogs>,
+}
+
+impl Debug for Settings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Settings")
+            .field("defaults", &self.defaults)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Settings {
+    pub fn new(
+        storage: Arc<ErasedStorage<UserSettings>>,
+        defaults: ExportOptions,
+        catalogs: Arc<Catalogs>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            storage,
+            defaults,
+            catalogs,
+        })
+    }
+
+    /// The settings of a user, the defaults if they never changed any.
+    pub async fn get(&self, user: UserId) -> anyhow::Result<UserSettings> {
+        Ok(self
+            .storage
+            .clone()
+            .get_dialogue(Namespace::UserSettings.key(user.0 as i64))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get settings: {}", e))?
+            .unwrap_or_default())
+    }
+
+    pub async fn set(&self, user: UserId, settings: UserSettings) -> anyhow::Result<()> {
+        self.storage
+            .clone()
+            .update_dialogue(Namespace::UserSettings.key(user.0 as i64), settings)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update settings: {}", e))
+    }
+
+    /// The export options of a user, the bot's defaults for messages sent on behalf of a chat.
+    pub async fn options(&self, user: Option<&User>) -> anyhow::Result<ExportOptions> {
+        match user {
+            Some(user) => Ok(self.get(user.id).await?.resolve(&self.defaults)),
+            None => Ok(self.defaults.clone()),
+        }
+    }
+
+    /// The language of a user, English for messages sent on behalf of a chat.
+    pub async fn locale(&self, user: Option<&User>) -> anyhow::Result<Locale> {
+        let language = match user {
+            Some(user) => self
+                .get(user.id)
+                .await?
+                .language
+                .or_else(|| user.language_code.clone()),
+            None => None,
+        };
+
+        Ok(self.catalogs.locale(language.as_deref()))
+    }
+}
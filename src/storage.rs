@@ -0,0 +1,143 @@
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use teloxide::dispatching::dialogue::{
+    ErasedStorage, InMemStorage, Serializer, SqliteStorage, Storage,
+};
+use teloxide::types::ChatId;
+
+use crate::handlers::State;
+
+/// A value kept in a [`StorageBackend`], serialized along with its version.
+pub trait Versioned:
+    Serialize + DeserializeOwned + Default + Clone + Debug + Send + Sync + 'static
+{
+    /// Current version of the serialized value.
+    ///
+    /// Bump it and add a migration to [`Versioned::MIGRATIONS`] whenever a change to the
+    /// type can't be covered by `#[serde(default)]`, e.g. renamed variants or fields.
+    const VERSION: u64;
+
+    /// `MIGRATIONS[n]` upgrades a serialized version `n` value to `n + 1`.
+    const MIGRATIONS: &'static [fn(Value) -> Value];
+}
+
+impl Versioned for State {
+    const VERSION: u64 = 0;
+
+    const MIGRATIONS: &'static [fn(Value) -> Value] = &[];
+}
+
+/// Keys of the values kept besides dialogue states, which are keyed by chat id, so that
+/// both can share a database.
+///
+/// Telegram ids take up at most 52 bits, every namespace starts far above them.
+#[derive(Clone, Copy, Debug)]
+pub enum Namespace {
+    UserSettings = 1,
+    GroupSettings = 2,
+}
+
+impl Namespace {
+    /// The key of the value of a user or chat in this namespace.
+    pub fn key(self, id: i64) -> ChatId {
+        ChatId(((self as i64) << 56) + id)
+    }
+}
+
+/// Dialogue storage shared by all backends.
+pub type StateStorage = ErasedStorage<State>;
+
+/// Where dialogue states and settings are kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// Lost on restart.
+    Memory,
+    /// SQLite database at the given path.
+    Sqlite(String),
+    /// Redis server at the given URL.
+    #[cfg(feature = "redis-storage")]
+    Redis(String),
+}
+
+impl FromStr for StorageBackend {
+    type Err = anyhow::Error;
+
+    /// Parse `memory`, `sqlite:<path>` or `redis://<host>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "memory" => Ok(StorageBackend::Memory),
+            s if s.starts_with("sqlite:") => Ok(StorageBackend::Sqlite(
+                s.trim_start_matches("sqlite:")
+                    .trim_start_matches("//")
+                    .to_string(),
+            )),
+            #[cfg(feature = "redis-storage")]
+            s if s.starts_with("redis://") || s.starts_with("rediss://") => {
+                Ok(StorageBackend::Redis(s.to_string()))
+            }
+            s => Err(anyhow::anyhow!("Unsupported storage: {}", s)),
+        }
+    }
+}
+
+impl StorageBackend {
+    /// Open the storage, creating the database if needed.
+    pub async fn open<D: Versioned>(&self) -> anyhow::Result<Arc<ErasedStorage<D>>> {
+        Ok(match self {
+            StorageBackend::Memory => InMemStorage::<D>::new().erase(),
+            StorageBackend::Sqlite(path) => SqliteStorage::open(path, VersionedJson)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to open SQLite storage: {}", e))?
+                .erase(),
+            #[cfg(feature = "redis-storage")]
+            StorageBackend::Redis(url) => {
+                teloxide::dispatching::dialogue::RedisStorage::open(url.as_str(), VersionedJson)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to open Redis storage: {}", e))?
+                    .erase()
+            }
+        })
+    }
+}
+
+/// JSON serializer that stores values along with their version, and migrates values
+/// of older versions when loading them.
+pub struct VersionedJson;
+
+impl<D: Versioned> Serializer<D> for VersionedJson {
+    type Error = serde_json::Error;
+
+    fn serialize(&self, value: &D) -> Result<Vec<u8>, Self::Error> {
+        serde_json::to_vec(&json!({
+            "version": D::VERSION,
+            "state": value,
+        }))
+    }
+
+    fn deserialize(&self, data: &[u8]) -> Result<D, Self::Error> {
+        let value: Value = serde_json::from_slice(data)?;
+
+        let (version, mut state) = match value {
+            Value::Object(mut object) if object.contains_key("version") => (
+                object.get("version").and_then(Value::as_u64).unwrap_or(0),
+                object.remove("state").unwrap_or_default(),
+            ),
+            state => (0, state),
+        };
+
+        for migration in D::MIGRATIONS.iter().skip(version as usize) {
+            state = migration(state);
+        }
+
+        // a value written by a newer version, or one that no longer parses, starts over
+        Ok(serde_json::from_value(state).unwrap_or_else(|e| {
+            log::warn!("Discarding stored value of version {}: {}", version, e);
+            D::default()
+        }))
+    }
+}
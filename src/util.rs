@@ -1,28 +1,36 @@
 use std::io::Cursor;
-use std::process::Command;
 
 use anyhow::Context;
-use image::ImageFormat;
-use image::io::Reader as ImageReader;
-use infer::Infer;
-use teloxide::Bot;
 use teloxide::net::Download;
+use teloxide::payloads::EditMessageTextSetters;
 use teloxide::prelude::{Request, Requester};
-use teloxide::types::Sticker;
+use teloxide::types::{Message, Sticker};
+use teloxide::Bot;
 use tokio::fs;
 
-/// Get the value of an environment variable or a default value.
-#[tracing::instrument]
-pub fn env_or_default(key: &str, default: &str) -> String {
-    std::env::var(key).unwrap_or_else(|_| default.to_string())
+use crate::convert::transcoder::Transcoder;
+use crate::convert::{convert_sticker, AnimationOptions, OutputFormat};
+
+/// Edit the text of a status message, keeping its buttons.
+pub async fn edit_status(bot: &Bot, status: &Message, text: String) -> anyhow::Result<()> {
+    let mut request = bot.edit_message_text(status.chat.id, status.id, text);
+    if let Some(markup) = status.reply_markup() {
+        request = request.reply_markup(markup.clone());
+    }
+    request.send().await?;
+
+    Ok(())
 }
 
-/// Export a single sticker.
+/// Export a single sticker, returning the file extension and the converted data.
 #[tracing::instrument]
 pub async fn export_single_sticker(
     bot: Bot,
+    transcoder: &Transcoder,
     sticker: &Sticker,
-) -> anyhow::Result<(String, Vec<u8>)> {
+    format: OutputFormat,
+    animation: AnimationOptions,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
     // download the sticker file
     let file = bot
         .get_file(sticker.file.id.clone())
@@ -41,83 +49,146 @@ pub async fn export_single_sticker(
         file_data
     };
 
-    // infer the file type
-    let infer = Infer::new();
-    let kind = infer.get(&file_data).context("Failed to infer file type")?;
+    convert_sticker(
+        transcoder,
+        file_data,
+        &sticker.file.unique_id,
+        format,
+        animation,
+    )
+    .await
+}
 
-    // handle the file type
-    let mime = kind.mime_type();
-    match mime.split('/').next().unwrap_or_default() {
-        "image" => {
-            let data =
-                convert_unknown_image_to_png(&file_data).context("Failed to convert image")?;
+/// Hosts of `t.me` style links.
+const TELEGRAM_HOSTS: [&str; 3] = ["t.me/", "telegram.me/", "telegram.dog/"];
 
-            Ok((format!("{}.png", sticker.file.unique_id), data))
-        }
-        "video" => {
-            let data = convert_webm_to_gif(&file_data)
-                .await
-                .context("Failed to convert video")?;
+/// Extract sticker set names from `t.me/addstickers/<name>` and `t.me/addemoji/<name>`
+/// links (or their `tg://` forms) in a text, or from a text that is just a set name.
+pub fn parse_sticker_set_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
 
-            Ok((format!("{}.gif", sticker.file.unique_id), data))
+    for token in text.split_whitespace() {
+        if let Some(name) = parse_sticker_set_link(token) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
         }
-        _ => Err(anyhow::anyhow!("Unsupported file type")),
     }
-}
 
-/// Convert an unknown image to PNG format.
-#[tracing::instrument]
-pub fn convert_unknown_image_to_png(image: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let img = ImageReader::new(Cursor::new(image))
-        .with_guessed_format()?
-        .decode()
-        .context("Failed to decode image")?;
+    if names.is_empty() && is_sticker_set_name(text.trim()) {
+        names.push(text.trim().to_string());
+    }
+
+    names
+}
 
-    let mut buf = Vec::new();
-    img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
-        .context("Failed to encode image")?;
+/// Parse a single sticker set link.
+pub fn parse_sticker_set_link(link: &str) -> Option<String> {
+    let link = link.trim_end_matches(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+
+    let name = if let Some(query) = link
+        .strip_prefix("tg://addstickers?")
+        .or_else(|| link.strip_prefix("tg://addemoji?"))
+    {
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("set="))?
+    } else {
+        let link = link
+            .strip_prefix("https://")
+            .or_else(|| link.strip_prefix("http://"))
+            .unwrap_or(link);
+        let link = link.strip_prefix("www.").unwrap_or(link);
+        let path = TELEGRAM_HOSTS
+            .iter()
+            .find_map(|host| link.strip_prefix(host))?;
+
+        path.strip_prefix("addstickers/")
+            .or_else(|| path.strip_prefix("addemoji/"))?
+            .split(['/', '?', '#'])
+            .next()?
+    };
 
-    Ok(buf)
+    is_sticker_set_name(name).then(|| name.to_string())
 }
 
-/// Convert a webm video to a GIF.
-#[tracing::instrument]
-pub async fn convert_webm_to_gif(video: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let temp_dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
-    log::debug!("Temporary directory: {:?}", temp_dir.path());
+/// Whether a text looks like a sticker set name: up to 64 letters, digits and
+/// underscores, starting with a letter.
+pub fn is_sticker_set_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
 
-    let video_path = &temp_dir.path().join("video.webm");
-    let gif_path = &temp_dir.path().join("video.gif");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sticker_set_links() {
+        for link in [
+            "https://t.me/addstickers/Cats_2",
+            "http://t.me/addstickers/Cats_2",
+            "t.me/addstickers/Cats_2",
+            "https://www.telegram.me/addstickers/Cats_2",
+            "telegram.dog/addstickers/Cats_2",
+            "https://t.me/addemoji/Cats_2",
+            "https://t.me/addstickers/Cats_2/",
+            "https://t.me/addstickers/Cats_2?startapp=1",
+            "https://t.me/addstickers/Cats_2#top",
+            "tg://addstickers?set=Cats_2",
+            "tg://addemoji?foo=bar&set=Cats_2",
+            // punctuation around links in a sentence
+            "https://t.me/addstickers/Cats_2).",
+        ] {
+            assert_eq!(
+                parse_sticker_set_link(link).as_deref(),
+                Some("Cats_2"),
+                "{}",
+                link
+            );
+        }
+    }
 
-    fs::write(&video_path, video)
-        .await
-        .context("Failed to write video to disk")?;
-
-    let output = Command::new("ffmpeg")
-        .args([
-            "-i",
-            video_path.to_str().unwrap(),
-            "-vf",
-            "fps=30,scale=320:-1:flags=lanczos",
-            "-c:v",
-            "gif",
-            "-f",
-            "gif",
-            gif_path.to_str().unwrap(),
-        ])
-        .output()
-        .context("Failed to convert video to GIF")?;
-
-    if !output.status.success() {
-        return Err(anyhow::anyhow!(
-            "Failed to convert video to GIF: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    #[test]
+    fn rejects_other_links() {
+        for link in [
+            "https://t.me/Cats_2",
+            "https://t.me/addstickers/",
+            "https://t.me/addstickers/2cats",
+            "https://t.me/addstickers/ca-ts",
+            "https://example.com/addstickers/Cats_2",
+            "tg://addstickers?name=Cats_2",
+            "Cats_2",
+        ] {
+            assert_eq!(parse_sticker_set_link(link), None, "{}", link);
+        }
     }
 
-    let gif = tokio::fs::read(&gif_path)
-        .await
-        .context("Failed to read GIF from disk")?;
+    #[test]
+    fn validates_sticker_set_names() {
+        assert!(is_sticker_set_name("a"));
+        assert!(is_sticker_set_name("Cats_by_bot"));
+        assert!(is_sticker_set_name(&"a".repeat(64)));
+        assert!(!is_sticker_set_name(""));
+        assert!(!is_sticker_set_name(&"a".repeat(65)));
+        assert!(!is_sticker_set_name("_cats"));
+        assert!(!is_sticker_set_name("1cats"));
+        assert!(!is_sticker_set_name("cats!"));
+        assert!(!is_sticker_set_name("кошки"));
+    }
 
-    Ok(gif)
+    #[test]
+    fn parses_sticker_set_names_from_text() {
+        assert_eq!(
+            parse_sticker_set_names(
+                "look: t.me/addstickers/cats, t.me/addemoji/dogs and t.me/addstickers/cats"
+            ),
+            ["cats", "dogs"]
+        );
+        // a text that is just a name, but not a sentence
+        assert_eq!(parse_sticker_set_names("  cats \n"), ["cats"]);
+        assert!(parse_sticker_set_names("cats and dogs").is_empty());
+        assert!(parse_sticker_set_names("").is_empty());
+    }
 }
@@ -0,0 +1,132 @@
+use std::convert::Infallible;
+use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use teloxide::prelude::*;
+use teloxide::types::InputFile;
+use teloxide::update_listeners::webhooks::{self, Options};
+use teloxide::update_listeners::UpdateListener;
+
+/// Settings of the webhook listener, used instead of long polling when `update_source` is `webhook`.
+#[derive(Clone)]
+pub struct WebhookConfig {
+    /// Public URL Telegram sends the updates to.
+    pub url: reqwest::Url,
+    /// Address the HTTP server binds to.
+    pub address: SocketAddr,
+    /// Path the updates are served on, the path of `url` if unset.
+    pub path: Option<String>,
+    /// Expected in the `X-Telegram-Bot-Api-Secret-Token` header, generated if unset.
+    pub secret_token: Option<String>,
+    /// Serve HTTPS directly instead of behind a reverse proxy.
+    pub tls: Option<TlsFiles>,
+}
+
+/// PEM files of the certificate served by the webhook listener.
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+    /// Upload the certificate to Telegram, required for self-signed ones.
+    pub self_signed: bool,
+}
+
+impl Debug for WebhookConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("url", &self.url.as_str())
+            .field("address", &self.address)
+            .field("path", &self.path)
+            .field("tls", &self.tls)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Start the webhook server and register it with Telegram.
+///
+/// The returned listener feeds the dispatcher, the webhook is deleted once it stops.
+#[tracing::instrument(skip(bot))]
+pub async fn listen(
+    bot: Bot,
+    config: WebhookConfig,
+) -> anyhow::Result<impl UpdateListener<Err = Infallible>> {
+    let mut options = Options::new(config.address, config.url.clone());
+    if let Some(secret_token) = config.secret_token {
+        options = options.secret_token(secret_token);
+    }
+    let secret_token = options.get_or_gen_secret_token().to_string();
+
+    // Fail before registering the webhook if the server can't start
+    let socket = std::net::TcpListener::bind(config.address)
+        .with_context(|| format!("Failed to bind {}", config.address))?;
+    socket.set_nonblocking(true)?;
+    let tls = match &config.tls {
+        Some(tls) => Some(
+            RustlsConfig::from_pem_file(&tls.certificate, &tls.key)
+                .await
+                .context("Failed to load the TLS certificate")?,
+        ),
+        None => None,
+    };
+
+    let mut request = bot
+        .set_webhook(config.url.clone())
+        .secret_token(secret_token);
+    if let Some(tls) = config.tls.as_ref().filter(|tls| tls.self_signed) {
+        request = request.certificate(InputFile::file(&tls.certificate));
+    }
+    request.send().await.context("Failed to set the webhook")?;
+
+    if let Some(path) = &config.path {
+        options.url.set_path(path);
+    }
+
+    let (mut listener, stop_flag, router) = webhooks::axum_no_setup(options);
+    let stop_token = listener.stop_token();
+    let shutdown = async move {
+        stop_flag.await;
+        if let Err(e) = bot.delete_webhook().send().await {
+            log::error!("Failed to delete the webhook: {}", e);
+        }
+    };
+
+    log::info!("Listening for webhook updates on {}", config.address);
+
+    match tls {
+        Some(tls) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            tokio::spawn(async move {
+                let server = axum_server::from_tcp_rustls(socket, tls)
+                    .handle(handle)
+                    .serve(router.into_make_service());
+                if let Err(e) = server.await {
+                    log::error!("Webhook server error: {}", e);
+                    stop_token.stop();
+                }
+            });
+        }
+        None => {
+            let server = axum::Server::from_tcp(socket)?
+                .serve(router.into_make_service())
+                .with_graceful_shutdown(shutdown);
+            tokio::spawn(async move {
+                if let Err(e) = server.await {
+                    log::error!("Webhook server error: {}", e);
+                    stop_token.stop();
+                }
+            });
+        }
+    }
+
+    Ok(listener)
+}